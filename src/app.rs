@@ -1,66 +1,130 @@
-use std::collections::btree_map::{BTreeMap, Entry};
+use std::collections::BTreeSet;
 use std::convert::TryFrom;
-use std::fs;
-use std::fs::{File, OpenOptions};
-use std::io::{BufRead, BufReader, LineWriter, Write};
-use std::path::{Path, PathBuf};
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicUsize, Ordering};
+pub use std::time::Duration;
 use std::time::Instant;
-pub use std::time::{Duration, SystemTime};
 
 use eframe::egui::{
-    Align, Align2, CentralPanel, Color32, CtxRef, Image, InnerResponse, Key, Painter, Pos2, Rect,
-    Sense, Stroke, TextEdit, TextStyle, TextureId, Ui, Vec2,
+    Align, Align2, CentralPanel, Color32, CtxRef, Image, InnerResponse, Key, Painter,
+    PointerButton, Pos2, Rect, Response, Sense, Stroke, TextEdit, TextStyle, TextureId, Ui, Vec2,
 };
 use eframe::epi::{Frame, Storage};
 use eframe::{egui, epi};
 
 use crate::app::arguments::Arguments;
 use crate::app::bbox::{BBox, BBoxError};
-use crate::app::drag_status::DragStatus;
-use crate::app::image_cache::{ImageCache, ImageLookup};
+use crate::app::classes::ClassEdit;
+use crate::app::drag_status::{DragKind, DragStatus};
+use crate::app::filter::NavFilter;
+use crate::app::history::History;
+use crate::app::image_cache::{ImageCache, ImageLookup, Region};
+use crate::app::image_file::{LabelFormat, LabelState, LabelStorage};
 use crate::app::images::Images;
 use crate::app::keyboard_mapping::zero_to_nine::ZeroToNine;
-use crate::app::keyboard_mapping::{Action, KeyboardMapping};
+use crate::app::keyboard_mapping::{Action, EventTrigger, KeyboardMapping, BINDABLE_KEYS};
+use crate::app::marks::{MarkCategory, Marks};
+use crate::app::orientation::LabelGrid;
 use crate::app::settings::Settings;
+use crate::app::stats::DatasetStats;
+use crate::app::tracking::Tracker;
+use crate::app::view::View;
 
 mod drag_status;
+mod filter;
+mod history;
 mod image_cache;
 mod image_file;
 mod images;
 pub mod keyboard_mapping;
+mod marks;
+mod orientation;
 mod settings;
+mod split;
+mod stats;
+mod tracking;
+mod validate;
+mod view;
+mod voc;
 
+/// how long `Q` waits for the number of the name to relabel a box as
+const RELABEL_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[allow(clippy::struct_excessive_bools)]
 pub struct RsMark {
     // index of box in current_boxes
     page: Page,
     settings: Settings,
     selected_box: Option<usize>,
+    // boxes under the pointer last frame, smallest first, and which of them is selected
+    hovered_boxes: Vec<usize>,
+    selection_cycle: usize,
     current_image_input_text: String,
     key_map: KeyboardMapping,
     current_index: AtomicUsize,
     images: Images,
     names: Vec<String>,
+    label_format: LabelFormat,
     selected_name: usize,
     image_cache: ImageCache,
     current_image: Option<(TextureId, Vec2, Color32)>,
+    // a sharper decode of the zoomed in part of the current image
+    zoomed_image: Option<(TextureId, Region, Rect)>,
+    view: View,
     current_boxes: Vec<BBox>,
+    history: History,
     drag: DragStatus,
     shortcut_buffer: Vec<(ZeroToNine, Instant)>,
-    stats: Stats,
+    // box that the next number shortcut relabels instead of selecting a name, and when that was
+    // asked for. Dropped when the boxes change, since the index may point at another box by then
+    pending_relabel: Option<(usize, Instant)>,
+    stats: DatasetStats,
     allow_number_shortcuts: bool,
-    marked_file: PathBuf,
-}
-
-#[derive(Default)]
-struct Stats {
-    annotation_freq: BTreeMap<String, u32>,
+    marks: Marks,
+    // where the file menu imports and exports COCO labels
+    coco_file: PathBuf,
+    // the file menu's import is waiting to be confirmed
+    confirm_coco_import: bool,
+    // shown above everything until dismissed, for errors we can recover from
+    error_banner: Option<String>,
+    // the current boxes differ from what is in the label file
+    unsaved_changes: bool,
+    nav_filter: NavFilter,
+    names_file: PathBuf,
+    // what the class manager's text fields hold, one per class and one for a new class
+    class_inputs: Vec<String>,
+    new_class_input: String,
+    // a merge or delete waiting to be confirmed
+    pending_class_edit: Option<ClassEdit>,
+    // what the mark category name fields hold, one per category
+    mark_inputs: Vec<String>,
+    // the action waiting for a key press on the key bindings page
+    rebinding: Option<Action>,
+    // why the last rebind was refused
+    rebind_conflict: Option<String>,
+    // the key bindings differ from keybindings.json
+    key_map_changed: bool,
+    // boxes copied to paste into another image, kept when the image changes
+    clipboard: Vec<BBox>,
+    tracker: Tracker,
+    // the image boxes are being tracked into
+    tracking_into: Option<usize>,
+    // the current boxes were tracked from the previous image, they are faded and not saved
+    // until they are accepted or one is edited. Never set while they are on disk, so dismissing
+    // them has nothing to undo there
+    suggested: bool,
 }
 
 impl RsMark {
+    #[allow(clippy::too_many_lines)]
     pub(crate) fn display_edit_settings(&mut self, ctx: &CtxRef, frame: &mut Frame<'_>) {
         self.top_bar_file_menu(ctx, frame);
         CentralPanel::default().show(ctx, |ui| {
+            if ui.button("Key bindings").clicked() {
+                self.page = Page::KeyBindings;
+            }
             ui.label("key_combo_trigger_ms");
             let mut key_combo_trigger_ms = self.settings.key_combo_trigger_ms.to_string();
             if ui.text_edit_singleline(&mut key_combo_trigger_ms).changed() {
@@ -106,7 +170,189 @@ impl RsMark {
                 }
             }
             ui.label("if checked, crosshair will always be the complementary color of the average color of the image");
-            ui.checkbox(&mut self.settings.dynamic_crosshair, "dynamic crosshair color")
+            ui.checkbox(&mut self.settings.dynamic_crosshair, "dynamic crosshair color");
+            ui.label("if checked, scrolling zooms the image instead of changing the name (ctrl + scroll always zooms)");
+            ui.checkbox(&mut self.settings.scroll_to_zoom, "scroll to zoom");
+            ui.label("if checked, moving to the next image suggests the boxes of this one where they moved to");
+            ui.checkbox(&mut self.settings.track_on_next, "track boxes into the next image");
+            ui.label("images are always displayed with their EXIF orientation applied, label files are stored against");
+            ui.radio_value(
+                &mut self.settings.label_grid,
+                LabelGrid::Oriented,
+                "the image as displayed",
+            );
+            ui.radio_value(
+                &mut self.settings.label_grid,
+                LabelGrid::Raw,
+                "the image as stored on disk",
+            );
+            self.edit_mark_categories(ui);
+        });
+        self.key_map.bind_marks(&self.settings.mark_categories);
+    }
+
+    fn edit_mark_categories(&mut self, ui: &mut Ui) {
+        ui.label("mark categories, each is toggled on the current image by its key. Renaming one keeps its marks, removing one unmarks its images");
+        if self.mark_inputs.len() != self.settings.mark_categories.len() {
+            self.mark_inputs = self
+                .settings
+                .mark_categories
+                .iter()
+                .map(|category| category.name.clone())
+                .collect();
+        }
+        let mut removed = None;
+        let mut renamed = None;
+        let mut rebound = None;
+        let key_map = &self.key_map;
+        let mark_inputs = &mut self.mark_inputs;
+        let categories = &self.settings.mark_categories;
+        for (i, (category, input)) in categories.iter().zip(mark_inputs.iter_mut()).enumerate() {
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(input);
+                let taken = categories.iter().any(|other| other.name == *input);
+                if taken && *input != category.name {
+                    ui.colored_label(Color32::RED, "already a category");
+                }
+                if ui
+                    .add_enabled(!taken && !input.is_empty(), egui::Button::new("Rename"))
+                    .clicked()
+                {
+                    renamed = Some((i, input.clone()));
+                }
+                egui::ComboBox::from_id_source(("mark key", i))
+                    .selected_text(format!("{:?}", category.key))
+                    .show_ui(ui, |ui| {
+                        for key in &BINDABLE_KEYS {
+                            if ui
+                                .selectable_label(category.key == *key, format!("{:?}", key))
+                                .clicked()
+                            {
+                                rebound = Some((i, *key));
+                            }
+                        }
+                    });
+                let action = u8::try_from(i).map(Action::Mark);
+                if let Some(other) = action
+                    .ok()
+                    .and_then(|action| key_map.bound_to(category.key, action))
+                {
+                    ui.colored_label(Color32::RED, format!("also bound to {:?}", other));
+                }
+                if ui.button("Remove").clicked() {
+                    removed = Some(i);
+                }
+            });
+        }
+        if let Some((i, key)) = rebound {
+            self.settings.mark_categories[i].key = key;
+        }
+        if let Some((i, name)) = renamed {
+            let old = self.settings.mark_categories[i].name.clone();
+            match self.marks.rename(&mut self.images, &old, &name) {
+                Ok(()) => self.settings.mark_categories[i].name = name,
+                Err(err) => self.error_banner = Some(format!("failed to save marks: {}", err)),
+            }
+        }
+        if let Some(i) = removed {
+            let name = self.settings.mark_categories[i].name.clone();
+            match self.marks.remove(&mut self.images, &name) {
+                Ok(()) => {
+                    self.settings.mark_categories.remove(i);
+                    self.mark_inputs.remove(i);
+                }
+                Err(err) => self.error_banner = Some(format!("failed to save marks: {}", err)),
+            }
+        }
+        // mark keys are bound to the key map above, so a free key is free of the categories too
+        let free_key = self.key_map.free_key();
+        let add = ui.add_enabled(free_key.is_some(), egui::Button::new("Add mark category"));
+        if free_key.is_none() {
+            ui.label("every key is taken, free one to add another mark category");
+        }
+        if let Some(key) = free_key.filter(|_| add.clicked()) {
+            let count = self.settings.mark_categories.len();
+            // one of these is free since there are only `count` categories
+            let name = (count..=count * 2)
+                .map(|n| format!("category {}", n))
+                .find(|name| {
+                    self.settings
+                        .mark_categories
+                        .iter()
+                        .all(|category| category.name != *name)
+                })
+                .expect("one of count + 1 names is free");
+            self.mark_inputs.push(name.clone());
+            self.settings
+                .mark_categories
+                .push(MarkCategory { name, key });
+        }
+    }
+
+    /// lists every action with its key, pressing a new key or mouse button rebinds it
+    fn display_key_bindings(&mut self, ctx: &CtxRef, frame: &mut Frame<'_>) {
+        self.top_bar_file_menu(ctx, frame);
+        if let Some(action) = self.rebinding {
+            if ctx.input().key_pressed(Key::Escape) {
+                self.rebinding = None;
+            } else if let Some(trigger) = EventTrigger::pressed(ctx) {
+                self.rebinding = None;
+                match self.key_map.rebind(action, trigger) {
+                    Ok(()) => {
+                        self.rebind_conflict = None;
+                        self.key_map_changed = true;
+                    }
+                    Err(other) => {
+                        self.rebind_conflict = Some(format!(
+                            "{} is already bound to {}, rebind that first",
+                            trigger, other
+                        ));
+                    }
+                }
+            }
+        }
+        let key_map = &mut self.key_map;
+        let rebinding = &mut self.rebinding;
+        let rebind_conflict = &mut self.rebind_conflict;
+        let key_map_changed = &mut self.key_map_changed;
+        let mark_categories = &self.settings.mark_categories;
+        CentralPanel::default().show(ctx, |ui| {
+            ui.label(format!(
+                "saved to {} next to settings.json, mark keys are set with their categories in settings",
+                KeyboardMapping::FILE_NAME
+            ));
+            if ui.button("Reset to defaults").clicked() {
+                key_map.reset();
+                *rebinding = None;
+                *rebind_conflict = None;
+                *key_map_changed = true;
+            }
+            if let Some(conflict) = rebind_conflict {
+                ui.colored_label(Color32::RED, conflict.as_str());
+            }
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::Grid::new("key bindings")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for (action, trigger) in key_map.iter() {
+                            ui.label(action.to_string());
+                            ui.label(trigger.to_string());
+                            if let Action::Mark(i) = action {
+                                ui.label(
+                                    mark_categories
+                                        .get(usize::from(*i))
+                                        .map_or("", |category| category.name.as_str()),
+                                );
+                            } else if *rebinding == Some(*action) {
+                                ui.label("press a key or mouse button, Escape cancels");
+                            } else if ui.button("Rebind").clicked() {
+                                *rebinding = Some(*action);
+                                *rebind_conflict = None;
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
         });
     }
 }
@@ -115,9 +361,159 @@ enum Page {
     Label,
     Settings,
     Stats,
+    Classes,
+    KeyBindings,
+}
+
+impl RsMark {
+    /// lists the classes with ways to add, rename, reorder, merge and delete them
+    fn display_classes(&mut self, ctx: &CtxRef, frame: &mut Frame<'_>) {
+        self.top_bar_file_menu(ctx, frame);
+        if self.class_inputs.len() != self.names.len() {
+            self.class_inputs.clone_from(&self.names);
+        }
+        let mut edit = None;
+        let names = &self.names;
+        let pending_edit = &mut self.pending_class_edit;
+        let class_inputs = &mut self.class_inputs;
+        let new_class_input = &mut self.new_class_input;
+        CentralPanel::default().show(ctx, |ui| {
+            if let Some(pending) = pending_edit.clone() {
+                ui.horizontal(|ui| {
+                    ui.colored_label(Color32::RED, pending.describe(names));
+                    if ui.button("Apply").clicked() {
+                        edit = Some(pending);
+                        *pending_edit = None;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        *pending_edit = None;
+                    }
+                });
+                ui.separator();
+            }
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                let last = names.len().saturating_sub(1);
+                for (i, input) in class_inputs.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.label(i.to_string());
+                        ui.text_edit_singleline(input);
+                        if ui
+                            .add_enabled(*input != names[i], egui::Button::new("Rename"))
+                            .clicked()
+                        {
+                            edit = Some(ClassEdit::Rename(i, input.clone()));
+                        }
+                        if ui.add_enabled(i > 0, egui::Button::new("Up")).clicked() {
+                            edit = Some(ClassEdit::Move { from: i, to: i - 1 });
+                        }
+                        if ui
+                            .add_enabled(i < last, egui::Button::new("Down"))
+                            .clicked()
+                        {
+                            edit = Some(ClassEdit::Move { from: i, to: i + 1 });
+                        }
+                        egui::ComboBox::from_id_source(("merge class", i))
+                            .selected_text("Merge into")
+                            .show_ui(ui, |ui| {
+                                for (into, name) in names.iter().enumerate() {
+                                    if into != i && ui.selectable_label(false, name).clicked() {
+                                        *pending_edit = Some(ClassEdit::Merge { from: i, into });
+                                    }
+                                }
+                            });
+                        if ui.button("Delete").clicked() {
+                            *pending_edit = Some(ClassEdit::Delete(i));
+                        }
+                    });
+                }
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(new_class_input);
+                    if ui.button("Add class").clicked() {
+                        edit = Some(ClassEdit::Add(new_class_input.clone()));
+                    }
+                });
+            });
+        });
+        if let Some(edit) = edit {
+            self.apply_class_edit(&edit);
+        }
+    }
+
+    /// renumbers every label file and rewrites the names file for `edit`, boxes are saved first
+    /// and the undo history is cleared since it refers to the old class numbers.
+    fn apply_class_edit(&mut self, edit: &ClassEdit) {
+        if !self.save_unsaved_labels() {
+            return;
+        }
+        match classes::apply(edit, &self.images, self.label_storage(), &self.names_file) {
+            Ok((names, summary)) => {
+                println!("{}", summary);
+                self.selected_name = edit
+                    .remap(self.selected_name)
+                    .unwrap_or_default()
+                    .min(names.len() - 1);
+                if let NavFilter::Containing(name) = self.nav_filter {
+                    self.nav_filter = edit
+                        .remap(name)
+                        .map_or(NavFilter::All, NavFilter::Containing);
+                }
+                self.class_inputs = names.clone();
+                self.names = names;
+                if let ClassEdit::Add(_) = edit {
+                    self.new_class_input.clear();
+                }
+                self.history = History::default();
+                self.selected_box = None;
+                self.pending_relabel = None;
+                // copied and suggested boxes are not in any label file, so they are renumbered
+                // here, and boxes still being tracked would come back numbered for the old classes
+                edit.remap_boxes(&mut self.clipboard);
+                self.tracking_into = None;
+                if self.suggested {
+                    edit.remap_boxes(&mut self.current_boxes);
+                    self.suggested = !self.current_boxes.is_empty();
+                } else {
+                    self.current_boxes = self.images[self.current_index.load(Ordering::SeqCst)]
+                        .img
+                        .load_labels(self.label_storage());
+                }
+                self.stats.scan_all(&self.images, self.label_storage());
+            }
+            Err(err) => self.error_banner = Some(format!("failed to edit classes: {}", err)),
+        }
+    }
 }
 
 impl RsMark {
+    /// the marks and label state of the current image, and what the keyboard is waiting for
+    fn display_status(&self, ui: &mut Ui) {
+        let marks = &self.images[self.current_index.load(Ordering::SeqCst)].marks;
+        if marks.is_empty() {
+            ui.label("UNMARKED");
+        } else {
+            ui.label(format!(
+                "MARKED: {}",
+                marks.iter().cloned().collect::<Vec<_>>().join(", ")
+            ));
+        }
+        ui.label(match self.current_label_state() {
+            LabelState::Unvisited => "UNVISITED",
+            LabelState::VisitedEmpty => "REVIEWED: NO OBJECTS",
+            LabelState::Labeled => "LABELED",
+        });
+        if self.pending_relabel.is_some() {
+            ui.label("RELABEL: type a name number");
+        }
+        if !self.clipboard.is_empty() {
+            ui.label(format!("COPIED: {} boxes", self.clipboard.len()));
+        }
+        if self.tracking_into.is_some() {
+            ui.label("TRACKING");
+        } else if self.suggested {
+            ui.label(format!("SUGGESTED: {} boxes", self.current_boxes.len()));
+        }
+    }
+
     pub(crate) fn display_info(
         &mut self,
         ctx: &CtxRef,
@@ -132,15 +528,25 @@ impl RsMark {
                     if ui.button("Stats").clicked() {
                         self.page = Page::Stats;
                     }
+                    if ui.button("Classes").clicked() {
+                        self.page = Page::Classes;
+                    }
+                    if ui.button("Import COCO").clicked() {
+                        self.confirm_coco_import = true;
+                    }
+                    if ui.button("Export COCO").clicked() {
+                        self.export_coco();
+                    }
                     if ui.button("Quit").clicked() {
                         frame.quit();
                     }
                 });
                 if ui.button("Prev").clicked() {
-                    self.handle_index_change(-1);
+                    self.step(false);
                 } else if ui.button("Next").clicked() {
-                    self.handle_index_change(1);
+                    self.step(true);
                 }
+                self.display_filter(ui);
                 let button_resp = ui.button("Jump to image:");
                 let resp = ui.add(
                     TextEdit::singleline(&mut self.current_image_input_text).desired_width(10.0),
@@ -155,11 +561,7 @@ impl RsMark {
                         .to_str()
                         .unwrap(),
                 );
-                ui.label(if curr_image.marked {
-                    "MARKED"
-                } else {
-                    "UNMARKED"
-                });
+                self.display_status(ui);
                 if ctx.input().keys_down.iter().any(|key| {
                     !matches!(
                         key,
@@ -209,56 +611,104 @@ impl RsMark {
 }
 
 pub mod arguments;
+mod atomic_file;
 mod bbox;
+mod classes;
+mod coco;
+mod commands;
 
 impl RsMark {
     #[must_use]
     pub fn yolo(
         Arguments {
-            image_dir,
+            mut image_dir,
             names,
+            names_file,
             names_dir,
+            format,
+            ..
         }: Arguments,
-        key_map: KeyboardMapping,
+        mut key_map: KeyboardMapping,
     ) -> RsMark {
         println!("found {} images!", image_dir.len());
+        let class_inputs = names.clone();
+        let image_count = image_dir.len();
         let settings = Settings::from_file().unwrap_or_default();
         let start_index = usize::min(image_dir.len() - 1, settings.start_img_index);
-        let marked_file = names_dir.join(Path::new(
-            format!(
-                "marked_{}.txt",
-                SystemTime::now()
-                    .duration_since(SystemTime::UNIX_EPOCH)
-                    .unwrap_or_else(|_| Duration::new(0, 0))
-                    .as_secs()
-            )
-            .as_str(),
-        ));
+        key_map.bind_marks(&settings.mark_categories);
+        // mark keys come from the settings, so they can only be checked against the other
+        // bindings once both are loaded
+        let conflicts = key_map
+            .conflicts()
+            .iter()
+            .map(|(trigger, a, b)| format!("{} is bound to both {} and {}", trigger, a, b))
+            .collect::<Vec<_>>();
+        for conflict in &conflicts {
+            println!("WARNING: {}", conflict);
+        }
+        let marks = Marks::load(
+            &names_dir,
+            &mut image_dir,
+            settings
+                .mark_categories
+                .first()
+                .map_or("marked", |category| category.name.as_str()),
+        );
         RsMark {
             page: Page::Label,
             settings,
             selected_box: None,
+            hovered_boxes: Vec::new(),
+            selection_cycle: 0,
             current_image_input_text: 0.to_string(),
             key_map,
             current_index: AtomicUsize::new(start_index),
             images: image_dir,
             names,
+            label_format: format,
             selected_name: 0,
             image_cache: ImageCache::new(Vec2::new(500.0, 500.0)),
             current_image: None,
+            zoomed_image: None,
+            view: View::default(),
             current_boxes: Vec::new(),
+            history: History::default(),
             drag: DragStatus::empty(),
             shortcut_buffer: Vec::new(),
-            stats: Stats::default(),
+            pending_relabel: None,
+            stats: DatasetStats::new(image_count),
             allow_number_shortcuts: true,
-            marked_file,
+            marks,
+            coco_file: names_dir.join("instances.json"),
+            confirm_coco_import: false,
+            error_banner: (!conflicts.is_empty())
+                .then(|| format!("{}, change the mark key in settings", conflicts.join(", "))),
+            unsaved_changes: false,
+            nav_filter: NavFilter::All,
+            names_file,
+            class_inputs,
+            new_class_input: String::new(),
+            pending_class_edit: None,
+            mark_inputs: Vec::new(),
+            rebinding: None,
+            rebind_conflict: None,
+            key_map_changed: false,
+            clipboard: Vec::new(),
+            tracker: Tracker::new(),
+            tracking_into: None,
+            suggested: false,
         }
     }
 
-    /// # Panics
-    /// this will panic if for whatever reason, the recently drawn labels fail to save.
-    /// This is intended to prevent the user from labeling images for hours and nothing saving.
+    /// saves the boxes on the current image if they changed and moves `incr` images along. If
+    /// saving fails we stay on the current image and show the error so nothing drawn is lost.
+    ///
+    /// Images that were only looked at are not saved, so they stay unvisited until they are
+    /// confirmed.
     pub fn handle_index_change(&mut self, incr: isize) {
+        if !self.save_unsaved_labels() {
+            return;
+        }
         let mut reverted_index = false;
         let prev_index = if incr.is_negative() {
             self.current_index
@@ -268,11 +718,7 @@ impl RsMark {
                 .fetch_add(incr.abs() as usize, Ordering::SeqCst)
         };
         let new_index = self.current_index.load(Ordering::SeqCst);
-        self.images[prev_index]
-            .img
-            .save_labels(&self.current_boxes)
-            .unwrap_or_else(|err| panic!("error occurred while writing label {}", err));
-        self.current_boxes = self
+        let boxes = self
             .images
             .get(new_index)
             .unwrap_or_else(|| {
@@ -281,7 +727,17 @@ impl RsMark {
                 reverted_index = true;
                 &self.images[prev_index].img
             })
-            .load_labels();
+            .load_labels(self.label_storage());
+        // suggestions are not on disk, a refresh of the same image (e.g. on resize) keeps them
+        if self.current_index.load(Ordering::SeqCst) != prev_index {
+            self.suggested = false;
+            self.tracking_into = None;
+        }
+        if !self.suggested {
+            self.current_boxes = boxes;
+        }
+        self.pending_relabel = None;
+        self.view = View::default();
         self.current_image_input_text = {
             if reverted_index {
                 prev_index
@@ -296,22 +752,32 @@ impl RsMark {
 
 impl epi::App for RsMark {
     fn update(&mut self, ctx: &CtxRef, frame: &mut Frame<'_>) {
+        self.display_error_banner(ctx);
+        self.display_import_confirmation(ctx);
         match &self.page {
             Page::Label => {
                 self.image_cache.update();
+                self.receive_suggestions();
+                if self.tracking_into.is_some() {
+                    ctx.request_repaint();
+                }
                 self.handle_key_presses(ctx);
                 self.display_info(ctx, frame);
                 self.display_names(ctx);
                 self.display_images(ctx, frame);
             }
             Page::Settings => self.display_edit_settings(ctx, frame),
+            Page::Classes => self.display_classes(ctx, frame),
+            Page::KeyBindings => self.display_key_bindings(ctx, frame),
             Page::Stats => {
                 self.top_bar_file_menu(ctx, frame);
+                self.stats.update();
                 CentralPanel::default().show(ctx, |ui| {
-                    for (name, freq) in &self.stats.annotation_freq {
-                        ui.label(format!("{}: {}", name, freq));
-                    }
+                    egui::ScrollArea::vertical().show(ui, |ui| self.stats.display(ui, &self.names));
                 });
+                if self.stats.scanning() {
+                    ctx.request_repaint();
+                }
             }
         }
     }
@@ -325,20 +791,18 @@ impl epi::App for RsMark {
         self.image_cache.update();
         self.current_boxes = self.images[self.current_index.load(Ordering::SeqCst)]
             .img
-            .load_labels();
+            .load_labels(self.label_storage());
+        self.stats.scan_all(&self.images, self.label_storage());
     }
 
     fn save(&mut self, _storage: &mut dyn Storage) {
         self.settings.start_img_index = self.current_index.load(Ordering::SeqCst);
-        self.images[self.current_index.load(Ordering::SeqCst)]
-            .img
-            .save_labels(&self.current_boxes)
-            .unwrap_or_else(|err| {
-                println!(
-                    "FAILED TO SAVE FINAL ANNOTATIONS ON EXIT {:#?} \n\n DUE TO {}",
-                    self.current_boxes, err
-                );
-            });
+        if !self.save_unsaved_labels() {
+            println!(
+                "FAILED TO SAVE ANNOTATIONS {:#?} \n\n DUE TO {:?}",
+                self.current_boxes, self.error_banner
+            );
+        }
         match File::options()
             .create(true)
             .write(true)
@@ -362,6 +826,12 @@ impl epi::App for RsMark {
                 );
             }
         }
+        if self.key_map_changed {
+            match self.key_map.save() {
+                Ok(()) => self.key_map_changed = false,
+                Err(err) => println!("FAILED TO SAVE KEY BINDINGS due to {}", err),
+            }
+        }
     }
 
     fn name(&self) -> &str {
@@ -374,16 +844,390 @@ impl epi::App for RsMark {
 }
 
 impl RsMark {
-    fn handle_key_presses(&mut self, ctx: &CtxRef) {
-        if self.key_map.is_triggered(Action::Clear, ctx) {
-            self.current_boxes.clear();
+    /// applies `edit` to the current boxes, remembering the previous boxes if anything changed
+    fn edit_boxes(&mut self, edit: impl FnOnce(&mut Vec<BBox>)) {
+        let before = self.current_boxes.clone();
+        edit(&mut self.current_boxes);
+        if before != self.current_boxes {
+            self.history
+                .record(self.current_index.load(Ordering::SeqCst), before);
+            self.unsaved_changes = true;
+            self.pending_relabel = None;
+            // adjusting a suggestion confirms all of them
+            self.suggested = false;
         }
+    }
+
+    fn relabel(&mut self, box_inx: usize, name: usize) {
+        if box_inx < self.current_boxes.len() {
+            self.edit_boxes(|boxes| boxes[box_inx] = boxes[box_inx].with_name(name));
+        }
+    }
+
+    fn handle_history_keys(&mut self, ctx: &CtxRef) {
+        let index = self.current_index.load(Ordering::SeqCst);
+        let restored = if self.key_map.is_triggered(Action::Undo, ctx) {
+            self.history.undo(index, &self.current_boxes)
+        } else if self.key_map.is_triggered(Action::Redo, ctx) {
+            self.history.redo(index, &self.current_boxes)
+        } else {
+            None
+        };
+        if let Some(boxes) = restored {
+            self.current_boxes = boxes;
+            self.unsaved_changes = true;
+            self.suggested = false;
+            self.pending_relabel = None;
+            self.drag.clear();
+        }
+    }
+
+    fn handle_clipboard_keys(&mut self, ctx: &CtxRef) {
+        if self.key_map.is_triggered(Action::CopyAll, ctx) {
+            self.clipboard.clone_from(&self.current_boxes);
+        } else if self.key_map.is_triggered(Action::Copy, ctx) {
+            self.clipboard = match self.selected_box.and_then(|i| self.current_boxes.get(i)) {
+                Some(bbox) => vec![bbox.clone()],
+                None => self.current_boxes.clone(),
+            };
+        }
+        let pasted = if self.key_map.is_triggered(Action::Paste, ctx) {
+            self.clipboard.clone()
+        } else if self.key_map.is_triggered(Action::CopyPrevious, ctx) {
+            let index = self.current_index.load(Ordering::SeqCst);
+            match index.checked_sub(1) {
+                Some(previous) => self.images[previous].img.load_labels(self.label_storage()),
+                None => Vec::new(),
+            }
+        } else {
+            Vec::new()
+        };
+        // pasting twice does not stack the same boxes on top of each other, and boxes of classes
+        // that are not in the names file cannot be drawn
+        let name_count = self.names.len();
+        self.edit_boxes(|boxes| {
+            for bbox in pasted {
+                if bbox.name < name_count && !boxes.contains(&bbox) {
+                    boxes.push(bbox);
+                }
+            }
+        });
+    }
+
+    fn handle_navigation_keys(&mut self, ctx: &CtxRef) {
         if self.key_map.is_triggered(Action::NextImage, ctx) {
-            self.handle_index_change(1);
+            self.step(true);
         }
         if self.key_map.is_triggered(Action::PrevImage, ctx) {
-            self.handle_index_change(-1);
+            self.step(false);
+        }
+        if self.key_map.is_triggered(Action::NextUnvisited, ctx) {
+            self.jump_to_unvisited(true);
+        }
+        if self.key_map.is_triggered(Action::PrevUnvisited, ctx) {
+            self.jump_to_unvisited(false);
+        }
+        if self.key_map.is_triggered(Action::ConfirmReviewed, ctx) {
+            self.save_current_labels();
+        }
+    }
+
+    /// moves to the closest image after (or before) this one that has no label file
+    fn jump_to_unvisited(&mut self, forward: bool) {
+        self.jump_to_match(NavFilter::Unvisited, forward);
+    }
+
+    /// moves to the next (or previous) image that passes the navigation filter
+    fn step(&mut self, forward: bool) {
+        match self.nav_filter {
+            NavFilter::All if forward && self.settings.track_on_next => self.track_to_next(),
+            NavFilter::All => self.handle_index_change(if forward { 1 } else { -1 }),
+            filter => self.jump_to_match(filter, forward),
+        }
+    }
+
+    /// moves to the next image and starts tracking the boxes of this one into it, unless it
+    /// already has boxes
+    fn track_to_next(&mut self) {
+        let from = self.current_index.load(Ordering::SeqCst);
+        let boxes = self.current_boxes.clone();
+        self.handle_index_change(1);
+        let to = self.current_index.load(Ordering::SeqCst);
+        if to == from + 1 && !boxes.is_empty() && self.current_boxes.is_empty() {
+            self.tracker
+                .track(&self.images[from].img, to, &self.images[to].img, boxes);
+            self.tracking_into = Some(to);
         }
+    }
+
+    /// makes the boxes tracked into the current image its boxes once they are found, unless
+    /// boxes were drawn on it while tracking
+    fn receive_suggestions(&mut self) {
+        while let Some((index, suggestions)) = self.tracker.suggestions() {
+            if self.tracking_into == Some(index) {
+                self.tracking_into = None;
+                if self.current_boxes.is_empty() && !suggestions.is_empty() {
+                    self.current_boxes = suggestions;
+                    self.suggested = true;
+                    self.selected_box = None;
+                }
+            }
+        }
+    }
+
+    fn handle_suggestion_keys(&mut self, ctx: &CtxRef) {
+        if self.key_map.is_triggered(Action::TrackToNext, ctx) {
+            self.track_to_next();
+        }
+        // Enter and Backspace also edit the image number while it is being typed
+        if !self.suggested || !self.allow_number_shortcuts {
+            return;
+        }
+        if self.key_map.is_triggered(Action::AcceptSuggestions, ctx) {
+            // kept as unsaved boxes if saving fails, so moving on tries again instead of dropping them
+            self.unsaved_changes = true;
+            self.suggested = false;
+            self.save_current_labels();
+        } else if self.key_map.is_triggered(Action::DismissSuggestions, ctx) {
+            self.suggested = false;
+            self.current_boxes.clear();
+            self.selected_box = None;
+        }
+    }
+
+    /// moves to the closest image after (or before) this one that passes `filter`
+    fn jump_to_match(&mut self, filter: NavFilter, forward: bool) {
+        self.stats.update();
+        let curr = self.current_index.load(Ordering::SeqCst);
+        let (images, format, stats) = (&self.images, self.label_format, &self.stats);
+        // stops at images the stats have not read yet rather than guessing
+        let stop = |i: usize| match filter.matches(i, &images[i], format, stats) {
+            Some(false) => None,
+            Some(true) => Some((i, true)),
+            None => Some((i, false)),
+        };
+        let found = if forward {
+            (curr + 1..images.len()).find_map(stop)
+        } else {
+            (0..curr).rev().find_map(stop)
+        };
+        match found.map(|(i, known)| (isize::try_from(i), isize::try_from(curr), known)) {
+            Some((_, _, false)) => {
+                self.current_image_input_text = String::from("still reading label files");
+            }
+            Some((Ok(index), Ok(curr), true)) => self.handle_index_change(index - curr),
+            Some(_) => self.current_image_input_text = String::from("index too high"),
+            None => {
+                self.current_image_input_text =
+                    format!("no match: {}", filter.describe(&self.names));
+            }
+        }
+    }
+
+    /// picks which images Prev and Next stop at
+    fn display_filter(&mut self, ui: &mut Ui) {
+        let names = &self.names;
+        let mut filter = self.nav_filter;
+        let (name, boxes) = match filter {
+            NavFilter::Containing(name) => (name, 1),
+            NavFilter::MoreBoxesThan(n) | NavFilter::FewerBoxesThan(n) => (self.selected_name, n),
+            _ => (self.selected_name, 1),
+        };
+        egui::ComboBox::from_id_source("navigation filter")
+            .selected_text(format!("showing {}", filter.describe(names)))
+            .show_ui(ui, |ui| {
+                for kind in NavFilter::kinds(name, boxes).iter().copied() {
+                    if ui
+                        .selectable_label(filter.same_kind(kind), kind.describe(names))
+                        .clicked()
+                    {
+                        filter = kind;
+                    }
+                }
+            });
+        match &mut filter {
+            NavFilter::Containing(name) => {
+                egui::ComboBox::from_id_source("navigation filter name")
+                    .selected_text(names.get(*name).map_or("?", String::as_str))
+                    .show_ui(ui, |ui| {
+                        for (i, text) in names.iter().enumerate() {
+                            ui.selectable_value(name, i, text);
+                        }
+                    });
+            }
+            NavFilter::MoreBoxesThan(n) | NavFilter::FewerBoxesThan(n) => {
+                ui.add(egui::DragValue::new(n).speed(0.1));
+            }
+            _ => {}
+        }
+        self.nav_filter = filter;
+    }
+
+    /// what will be on disk for the current image once it is saved
+    fn current_label_state(&self) -> LabelState {
+        let image = &self.images[self.current_index.load(Ordering::SeqCst)].img;
+        if self.unsaved_changes || image.has_label_file(self.label_format) {
+            LabelState::of(&self.current_boxes)
+        } else {
+            LabelState::Unvisited
+        }
+    }
+
+    /// adds the current image to `category` or removes it if it was already in it
+    fn toggle_mark(&mut self, category: &str) {
+        let marks = &mut self.images[self.current_index.load(Ordering::SeqCst)].marks;
+        let toggle = |marks: &mut BTreeSet<String>| {
+            if !marks.remove(category) {
+                marks.insert(category.to_string());
+            }
+        };
+        toggle(marks);
+        if let Err(err) = self.marks.save(&self.images) {
+            toggle(&mut self.images[self.current_index.load(Ordering::SeqCst)].marks);
+            self.error_banner = Some(format!("failed to save marks: {}", err));
+        }
+    }
+
+    fn label_storage(&self) -> LabelStorage<'_> {
+        LabelStorage {
+            format: self.label_format,
+            grid: self.settings.label_grid,
+            names: &self.names,
+        }
+    }
+
+    /// writes the boxes on the current image to its label file, returning false and showing the
+    /// error banner if that fails.
+    fn save_current_labels(&mut self) -> bool {
+        let index = self.current_index.load(Ordering::SeqCst);
+        let image = &self.images[index].img;
+        match image.save_labels(&self.current_boxes, self.label_storage()) {
+            Ok(()) => {
+                self.unsaved_changes = false;
+                self.suggested = false;
+                self.stats.scan(index, image, self.label_storage());
+                true
+            }
+            Err(err) => {
+                self.error_banner = Some(format!(
+                    "failed to save the labels of {}: {}",
+                    image.file_name(),
+                    err
+                ));
+                false
+            }
+        }
+    }
+
+    /// saves the current image if it has been edited since it was last saved
+    fn save_unsaved_labels(&mut self) -> bool {
+        !self.unsaved_changes || self.save_current_labels()
+    }
+
+    /// replaces the labels of every image in the COCO file next to the names file
+    fn import_coco(&mut self) {
+        if !self.save_unsaved_labels() {
+            return;
+        }
+        match coco::import(&self.images, self.label_storage(), &self.coco_file) {
+            Ok(summary) => println!("{}", summary),
+            Err(err) => {
+                self.error_banner = Some(format!(
+                    "failed to import {}: {}",
+                    self.coco_file.display(),
+                    err
+                ));
+            }
+        }
+        self.stats.scan_all(&self.images, self.label_storage());
+        self.current_boxes = self.images[self.current_index.load(Ordering::SeqCst)]
+            .img
+            .load_labels(self.label_storage());
+        self.suggested = false;
+        self.tracking_into = None;
+    }
+
+    /// writes the labels of every image to a COCO file next to the names file
+    fn export_coco(&mut self) {
+        if !self.save_unsaved_labels() {
+            return;
+        }
+        match coco::export(&self.images, self.label_storage(), &self.coco_file) {
+            Ok(summary) => println!("{}", summary),
+            Err(err) => {
+                self.error_banner = Some(format!(
+                    "failed to export {}: {}",
+                    self.coco_file.display(),
+                    err
+                ));
+            }
+        }
+    }
+
+    /// asks before the labels of every image in the COCO file are replaced, there is no undo
+    fn display_import_confirmation(&mut self, ctx: &CtxRef) {
+        if !self.confirm_coco_import {
+            return;
+        }
+        egui::TopBottomPanel::top("import confirmation").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.colored_label(
+                    Color32::RED,
+                    format!(
+                        "replace the labels of every image with boxes in {}? this cannot be undone",
+                        self.coco_file.display()
+                    ),
+                );
+                if ui.button("Import").clicked() {
+                    self.confirm_coco_import = false;
+                    self.import_coco();
+                }
+                if ui.button("Cancel").clicked() {
+                    self.confirm_coco_import = false;
+                }
+            });
+        });
+    }
+
+    /// the last thing that went wrong, with a way to retry saving or dismiss it
+    fn display_error_banner(&mut self, ctx: &CtxRef) {
+        if let Some(err) = self.error_banner.clone() {
+            egui::TopBottomPanel::top("error banner").show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.colored_label(Color32::RED, err);
+                    if ui.button("Retry save").clicked() && self.save_current_labels() {
+                        self.error_banner = None;
+                    }
+                    if ui.button("Dismiss").clicked() {
+                        self.error_banner = None;
+                    }
+                });
+            });
+        }
+    }
+
+    fn handle_key_presses(&mut self, ctx: &CtxRef) {
+        if self.key_map.is_triggered(Action::Clear, ctx) {
+            self.edit_boxes(Vec::clear);
+        }
+        self.handle_history_keys(ctx);
+        self.handle_clipboard_keys(ctx);
+        self.handle_suggestion_keys(ctx);
+        if self.key_map.is_triggered(Action::ResetView, ctx) {
+            self.view = View::default();
+        }
+        if self.key_map.is_triggered(Action::CycleSelection, ctx) {
+            self.selection_cycle = self.selection_cycle.wrapping_add(1);
+        }
+        if let Some(box_inx) = self.selected_box {
+            if self.key_map.is_triggered(Action::RelabelBox, ctx) {
+                self.relabel(box_inx, self.selected_name);
+            }
+            if self.key_map.is_triggered(Action::RelabelBoxNumber, ctx) {
+                self.pending_relabel = Some((box_inx, Instant::now()));
+            }
+        }
+        self.handle_navigation_keys(ctx);
         if self.key_map.is_triggered(Action::NextName, ctx) {
             self.selected_name += 1;
             if self.selected_name >= self.names.len() {
@@ -398,40 +1242,17 @@ impl RsMark {
             }
         }
         if let Some(box_inx) = self.selected_box {
-            if self.key_map.is_triggered(Action::RemoveBox, ctx) {
-                self.current_boxes.remove(box_inx);
+            if self.key_map.is_triggered(Action::RemoveBox, ctx)
+                && box_inx < self.current_boxes.len()
+            {
+                self.edit_boxes(|boxes| {
+                    boxes.remove(box_inx);
+                });
             }
         }
-        if self.key_map.is_triggered(Action::MarkAsSpecial, ctx) {
-            let curr_image = &mut self.images[self.current_index.load(Ordering::SeqCst)];
-            if curr_image.marked {
-                let file = OpenOptions::new()
-                    .read(true)
-                    .write(true)
-                    .open(&self.marked_file)
-                    .unwrap();
-
-                let lines = BufReader::new(file)
-                    .lines()
-                    .map(Result::unwrap)
-                    .filter(|x| x.as_str() != curr_image.img.as_path().to_str().unwrap())
-                    .collect::<Vec<_>>()
-                    .join("\n");
-
-                fs::write(&self.marked_file, lines).expect("Can't write");
-                curr_image.marked = false;
-            } else {
-                let mut f = File::options()
-                    .create(true)
-                    .append(true)
-                    .open(&self.marked_file)
-                    .unwrap();
-                let file = &curr_image.img;
-                let buf = file.as_path();
-                let file_name = buf.to_str().unwrap();
-                f.write_all(file_name.as_bytes()).unwrap();
-                f.write_all(vec![b'\n'].as_slice()).unwrap();
-                curr_image.marked = true;
+        for (i, category) in (0..=u8::MAX).zip(self.settings.mark_categories.clone()) {
+            if self.key_map.is_triggered(Action::Mark(i), ctx) {
+                self.toggle_mark(&category.name);
             }
         }
         if let Some((_, t)) = self.shortcut_buffer.last() {
@@ -444,12 +1265,22 @@ impl RsMark {
                     })
                     .parse::<usize>()
                     .unwrap();
+                let pending_relabel = self.pending_relabel.take();
                 if self.names.len() > shortcut {
-                    self.selected_name = shortcut;
+                    match pending_relabel {
+                        Some((box_inx, _)) => self.relabel(box_inx, shortcut),
+                        None => self.selected_name = shortcut,
+                    }
                 }
                 self.shortcut_buffer.clear();
             }
         }
+        // a relabel no number followed is given up on, so a later number picks a name again
+        if let Some((_, t)) = self.pending_relabel {
+            if self.shortcut_buffer.is_empty() && t.elapsed() > RELABEL_TIMEOUT {
+                self.pending_relabel = None;
+            }
+        }
         if self.allow_number_shortcuts {
             for i in ZeroToNine::iter() {
                 if self.key_map.is_triggered(Action::NameNumber(i), ctx) {
@@ -458,6 +1289,9 @@ impl RsMark {
             }
         }
 
+        if self.settings.scroll_to_zoom {
+            return;
+        }
         if ctx.input().scroll_delta.y < -self.settings.scroll_thresh {
             self.selected_name = if self.selected_name + 1 >= self.names.len() {
                 0
@@ -481,7 +1315,10 @@ impl RsMark {
                 self.handle_index_change(0);
             }
             if let Some((texture_id, size, avg_color)) = self.current_image {
-                let img = Image::new(texture_id, size).sense(Sense::click_and_drag());
+                let (texture_id, uv) = self.visible_texture(texture_id);
+                let img = Image::new(texture_id, size)
+                    .uv(uv)
+                    .sense(Sense::click_and_drag());
                 let img_resp = ui.add(img);
                 let rect = Rect {
                     min: img_resp.rect.min,
@@ -490,47 +1327,21 @@ impl RsMark {
                         y: img_resp.rect.min.y + size.y,
                     },
                 };
-                if img_resp.drag_started() {
-                    self.drag.start(img_resp.interact_pointer_pos().unwrap());
-                }
-                if let Some(curr_drag_diff) = self.drag.drag_diff {
-                    self.drag.drag_diff = Some(curr_drag_diff + img_resp.drag_delta());
-                }
-                if img_resp.drag_released() {
-                    if let (Some(drag_srt), Some(drag_diff)) =
-                        (self.drag.drag_start, self.drag.drag_diff)
-                    {
-                        match BBox::from_two_points_and_rect(
-                            self.selected_name,
-                            rect,
-                            drag_srt,
-                            drag_diff,
-                        ) {
-                            Ok(bbox) => {
-                                match self
-                                    .stats
-                                    .annotation_freq
-                                    .entry(self.names[self.selected_name].clone())
-                                {
-                                    Entry::Vacant(v) => {
-                                        v.insert(1);
-                                    }
-                                    Entry::Occupied(mut o) => *o.get_mut() += 1,
-                                }
-                                self.current_boxes.push(bbox);
-                            }
-                            Err(err) => println!("error creating box {}", err),
-                        }
-                    }
-                    self.drag.clear();
-                }
+                self.handle_view_input(ctx, &img_resp, rect);
+                let image_rect = self.view.image_rect(rect);
+                self.handle_box_drag(ctx, &img_resp, image_rect);
                 let painter = &mut ui.painter_at(rect);
-                self.paint_boxes(&ui, painter);
+                self.paint_boxes(&ui, painter, image_rect);
                 self.draw_cursor(ctx, painter, avg_color);
+                self.update_zoomed_image(frame);
             } else {
+                if let Some((texture_id, ..)) = self.zoomed_image.take() {
+                    frame.tex_allocator().free(texture_id);
+                }
                 let get_result = self.image_cache.get(
                     ImageLookup {
                         index: self.current_index.load(Ordering::SeqCst),
+                        region: None,
                     },
                     self.images
                         .as_slice()
@@ -561,6 +1372,110 @@ impl RsMark {
         })
     }
 
+    /// the texture to draw and the part of it that is visible
+    fn visible_texture(&self, full_image: TextureId) -> (TextureId, Rect) {
+        let visible = self.view.visible();
+        match (self.zoomed_image, self.view.region()) {
+            (Some((texture_id, shown, bounds)), Some(region)) if shown == region => {
+                // the crop covers a little more than the view, out to whole pixels
+                let uv = |pos: Pos2| ((pos - bounds.min) / bounds.size()).to_pos2();
+                (
+                    texture_id,
+                    Rect::from_min_max(uv(visible.min), uv(visible.max)),
+                )
+            }
+            _ => (full_image, visible),
+        }
+    }
+
+    fn handle_view_input(&mut self, ctx: &CtxRef, img_resp: &Response, rect: Rect) {
+        if img_resp.dragged_by(PointerButton::Middle) {
+            self.view.pan(img_resp.drag_delta(), rect);
+        }
+        let mut zoom = ctx.input().zoom_delta();
+        if self.settings.scroll_to_zoom {
+            // same curve egui uses to turn ctrl + scroll into zoom
+            zoom *= (ctx.input().scroll_delta.y / 200.0).exp();
+        }
+        if let Some(pos) = img_resp.hover_pos() {
+            // changing the view mid drag would move the box out from under the cursor
+            if (zoom - 1.0).abs() > f32::EPSILON && self.drag.drag_diff.is_none() {
+                self.view.zoom_around(zoom, rect, pos);
+            }
+        }
+    }
+
+    fn update_zoomed_image(&mut self, frame: &mut Frame<'_>) {
+        let region = match self.view.region() {
+            Some(region) if !matches!(self.zoomed_image, Some((_, shown, _)) if shown == region) => {
+                region
+            }
+            _ => return,
+        };
+        let get_result = self.image_cache.get(
+            ImageLookup {
+                index: self.current_index.load(Ordering::SeqCst),
+                region: Some(region),
+            },
+            self.images
+                .as_slice()
+                .iter()
+                .map(|it| &it.img)
+                .collect::<Vec<_>>()
+                .as_slice(),
+        );
+        if let Some((img, _)) = get_result {
+            if !matches!(img.size_usize(), (0, _) | (_, 0)) {
+                let texture_id = frame
+                    .tex_allocator()
+                    .alloc_srgba_premultiplied(img.size_usize(), img.data.as_slice());
+                if let Some((old, ..)) = self.zoomed_image.replace((texture_id, region, img.bounds))
+                {
+                    frame.tex_allocator().free(old);
+                }
+            }
+        }
+    }
+
+    fn handle_box_drag(&mut self, ctx: &CtxRef, img_resp: &Response, image_rect: Rect) {
+        if img_resp.drag_started() && !img_resp.dragged_by(PointerButton::Middle) {
+            let pos = img_resp.interact_pointer_pos().unwrap();
+            let origin = ctx.input().pointer.press_origin().unwrap_or(pos);
+            match self.selected_box {
+                Some(index) => match self.current_boxes[index].handle_at(image_rect, origin) {
+                    Some(handle) => self.drag.start(pos, DragKind::Resize(index, handle)),
+                    None => self.drag.start(pos, DragKind::Move(index)),
+                },
+                None => self.drag.start(pos, DragKind::New),
+            }
+        }
+        if let Some(curr_drag_diff) = self.drag.drag_diff {
+            self.drag.drag_diff = Some(curr_drag_diff + img_resp.drag_delta());
+        }
+        if img_resp.drag_released() {
+            if let (Some(drag_srt), Some(drag_diff), DragKind::New) =
+                (self.drag.drag_start, self.drag.drag_diff, self.drag.kind)
+            {
+                match BBox::from_two_points_and_rect(
+                    self.selected_name,
+                    image_rect,
+                    drag_srt,
+                    drag_diff,
+                ) {
+                    Ok(bbox) => self.edit_boxes(|boxes| boxes.push(bbox)),
+                    Err(err) => println!("error creating box {}", err),
+                }
+            }
+            if let Some((index, edited)) = self.edited_box(image_rect) {
+                match edited {
+                    Ok(bbox) => self.edit_boxes(|boxes| boxes[index] = bbox),
+                    Err(err) => println!("error editing box {}", err),
+                }
+            }
+            self.drag.clear();
+        }
+    }
+
     fn draw_cursor(&mut self, ctx: &CtxRef, painter: &mut Painter, image_color: Color32) {
         let alpha = self.settings.cross_hair_alpha;
         if let Some(pos) = ctx.input().pointer.hover_pos() {
@@ -625,17 +1540,38 @@ impl RsMark {
         }
     }
 
-    fn paint_boxes(&mut self, ui: &&mut Ui, painter: &mut Painter) {
+    /// the box being moved or resized along with what it would become if the drag ended now
+    fn edited_box(&self, rect: Rect) -> Option<(usize, Result<BBox, BBoxError>)> {
+        let drag_diff = self.drag.drag_diff?;
+        match self.drag.kind {
+            DragKind::New => None,
+            DragKind::Move(index) => self
+                .current_boxes
+                .get(index)
+                .map(|bbox| (index, bbox.moved_on_rect(rect, drag_diff))),
+            DragKind::Resize(index, handle) => self
+                .current_boxes
+                .get(index)
+                .map(|bbox| (index, bbox.resized_on_rect(rect, handle, drag_diff))),
+        }
+    }
+
+    fn paint_boxes(&mut self, ui: &&mut Ui, painter: &mut Painter, image_rect: Rect) {
+        let previously_selected = self.selected_box;
         self.selected_box = None;
-        if let (Some(drag_start), Some(drag_diff)) = (self.drag.drag_start, self.drag.drag_diff) {
+        let edited = self.drag.edited_box();
+        let mut hovered = Vec::new();
+        if let (Some(drag_start), Some(drag_diff), DragKind::New) =
+            (self.drag.drag_start, self.drag.drag_diff, self.drag.kind)
+        {
             match BBox::from_two_points_and_rect(
                 self.selected_name,
-                painter.clip_rect(),
+                image_rect,
                 drag_start,
                 drag_diff,
             ) {
                 Ok(bbox) => {
-                    bbox.draw(painter, self.settings.bounding_box_alpha, true);
+                    bbox.draw(painter, image_rect, self.settings.bounding_box_alpha, true);
                 }
                 Err(BBoxError::InvalidField(_)) => { /*ignore invalid boxes when dragging due to logging noise*/
                 }
@@ -645,30 +1581,70 @@ impl RsMark {
                 }
             }
         }
+        // suggestions are faded until they are accepted or edited
+        let alpha = if self.suggested {
+            self.settings.bounding_box_alpha / 2
+        } else {
+            self.settings.bounding_box_alpha
+        };
         for (i, bbox) in self.current_boxes.iter().enumerate() {
-            let rect = bbox.draw(painter, self.settings.bounding_box_alpha, false);
+            if edited == Some(i) {
+                continue;
+            }
+            let rect = bbox.draw(painter, image_rect, alpha, false);
             if self.settings.display_bounding_box_name {
-                bbox.draw_text(
-                    painter,
-                    &self.names,
-                    rect,
-                    self.settings.bounding_box_alpha,
-                    false,
-                );
+                bbox.draw_text(painter, &self.names, rect, alpha, false);
             }
-            if ui.rect_contains_pointer(rect) {
-                if let Some(selected) = self.selected_box {
-                    if self.current_boxes[selected].is_larger(bbox) {
-                        self.selected_box = Some(i);
-                    }
-                } else {
-                    self.selected_box = Some(i);
-                }
+            if edited.is_none() && ui.rect_contains_pointer(rect.intersect(painter.clip_rect())) {
+                hovered.push(i);
+            }
+        }
+        // smallest first so inner boxes are selected before the ones around them
+        hovered.sort_by(|&a, &b| {
+            self.current_boxes[a]
+                .area()
+                .total_cmp(&self.current_boxes[b].area())
+        });
+        if hovered != self.hovered_boxes {
+            self.selection_cycle = 0;
+            self.hovered_boxes = hovered;
+        }
+        if !self.hovered_boxes.is_empty() {
+            self.selected_box =
+                Some(self.hovered_boxes[self.selection_cycle % self.hovered_boxes.len()]);
+        }
+        // the handles of the selected box hang off its edges, keep it selected while over them
+        if let (Some(prev), Some(pos)) = (previously_selected, ui.ctx().input().pointer.hover_pos())
+        {
+            if let Some(handle) = self
+                .current_boxes
+                .get(prev)
+                .and_then(|bbox| bbox.handle_at(image_rect, pos))
+            {
+                self.selected_box = Some(prev);
+                ui.ctx().output().cursor_icon = handle.cursor_icon();
             }
         }
-        if let Some(bbox) = self.selected_box {
-            let rect =
-                self.current_boxes[bbox].draw(painter, self.settings.bounding_box_alpha, true);
+        if let Some((index, result)) = self.edited_box(image_rect) {
+            self.selected_box = Some(index);
+            // fall back to drawing the box where it was if the edit is invalid
+            let bbox = result.unwrap_or_else(|_| self.current_boxes[index].clone());
+            let rect = bbox.draw(painter, image_rect, self.settings.bounding_box_alpha, true);
+            bbox.draw_text(
+                painter,
+                &self.names,
+                rect,
+                self.settings.bounding_box_alpha,
+                true,
+            );
+            bbox.draw_handles(painter, image_rect);
+        } else if let Some(bbox) = self.selected_box {
+            let rect = self.current_boxes[bbox].draw(
+                painter,
+                image_rect,
+                self.settings.bounding_box_alpha,
+                true,
+            );
             self.current_boxes[bbox].draw_text(
                 painter,
                 &self.names,
@@ -676,6 +1652,7 @@ impl RsMark {
                 self.settings.bounding_box_alpha,
                 true,
             );
+            self.current_boxes[bbox].draw_handles(painter, image_rect);
         };
     }
 }
@@ -7,13 +7,17 @@ use std::io::{BufRead, BufReader};
 use std::num::ParseIntError;
 use std::path::{Path, PathBuf};
 
+use crate::app::commands::{Command, Conversion};
+use crate::app::image_file::LabelFormat;
 use crate::app::images::Images;
+use crate::app::split::SplitOptions;
 
 pub enum ArgumentError {
     InvalidNumber(String),
     FileDoesNotExist(String),
     InvalidFileType(String),
     ReadError(String),
+    UnknownOption(String),
 }
 
 impl From<ParseIntError> for ArgumentError {
@@ -29,6 +33,7 @@ impl Display for ArgumentError {
             ArgumentError::FileDoesNotExist(explanation) => ("FileDoesNotExist", explanation),
             ArgumentError::InvalidFileType(explanation) => ("InvalidFileType", explanation),
             ArgumentError::ReadError(explanation) => ("ReadError", explanation),
+            ArgumentError::UnknownOption(explanation) => ("UnknownOption", explanation),
         };
         write!(f, "{}: {}", err, explanation)
     }
@@ -37,11 +42,125 @@ impl Display for ArgumentError {
 pub struct Arguments {
     pub image_dir: Images,
     pub names: Vec<String>,
+    /// the names file, written back when classes are edited
+    pub names_file: PathBuf,
     pub names_dir: PathBuf,
+    pub format: LabelFormat,
+    /// run this instead of opening the gui
+    pub command: Option<Command>,
+}
+
+/// the value after `option`
+fn value<'a>(
+    option: &str,
+    optional: &mut impl Iterator<Item = &'a String>,
+) -> Result<&'a String, ArgumentError> {
+    optional
+        .next()
+        .ok_or_else(|| ArgumentError::InvalidNumber(format!("{} expects a value after it", option)))
+}
+
+/// the value after `option` as a number in [0, 1]
+fn ratio<'a>(
+    option: &str,
+    optional: &mut impl Iterator<Item = &'a String>,
+) -> Result<f32, ArgumentError> {
+    match value(option, optional)?.parse::<f32>() {
+        Ok(ratio) if (0.0..=1.0).contains(&ratio) => Ok(ratio),
+        Ok(ratio) => Err(ArgumentError::InvalidNumber(format!(
+            "{} {} is not between 0 and 1",
+            option, ratio
+        ))),
+        Err(err) => Err(ArgumentError::InvalidNumber(format!("{} {}", option, err))),
+    }
+}
+
+fn parse_format(value: &str) -> Result<LabelFormat, ArgumentError> {
+    match value {
+        "darknet" => Ok(LabelFormat::Darknet),
+        "voc" => Ok(LabelFormat::Voc),
+        other => Err(ArgumentError::UnknownOption(format!(
+            "{} is not one of [darknet, voc]",
+            other
+        ))),
+    }
+}
+
+fn parse_options(
+    command: Option<&str>,
+    optional: &[String],
+) -> Result<(LabelFormat, Option<Command>), ArgumentError> {
+    let mut format = LabelFormat::Darknet;
+    let mut conversion = None;
+    let mut split = SplitOptions::default();
+    let mut json = false;
+    let mut optional = optional.iter();
+    while let Some(option) = optional.next() {
+        match (command, option.as_str()) {
+            (_, "--format") => format = parse_format(value(option, &mut optional)?)?,
+            (Some("convert"), "--to") => {
+                conversion = Some(match value(option, &mut optional)?.as_str() {
+                    "coco" => Conversion::ExportCoco(value(option, &mut optional)?.into()),
+                    other => Conversion::Labels(parse_format(other)?),
+                });
+            }
+            (Some("convert"), "--from") => {
+                conversion = match value(option, &mut optional)?.as_str() {
+                    "coco" => Some(Conversion::ImportCoco(value(option, &mut optional)?.into())),
+                    other => {
+                        return Err(ArgumentError::UnknownOption(format!(
+                            "can only convert from coco, not {}",
+                            other
+                        )))
+                    }
+                };
+            }
+            (Some("validate"), "--json") => json = true,
+            (Some("split"), "--valid") => split.valid = ratio(option, &mut optional)?,
+            (Some("split"), "--test") => split.test = ratio(option, &mut optional)?,
+            (Some("split"), "--seed") => split.seed = value(option, &mut optional)?.parse()?,
+            (Some("split"), "--stratify") => split.stratify = true,
+            _ => {
+                return Err(ArgumentError::UnknownOption(format!(
+                    "{} is not an option of {}",
+                    option,
+                    command.unwrap_or("the gui")
+                )))
+            }
+        }
+    }
+    let command = match command {
+        None => None,
+        Some("validate") => Some(Command::Validate { json }),
+        Some("stats") => Some(Command::Stats),
+        Some("convert") => Some(Command::Convert(conversion.ok_or_else(|| {
+            ArgumentError::UnknownOption(String::from(
+                "convert needs --to <darknet|voc|coco <file>> or --from coco <file>",
+            ))
+        })?)),
+        Some("split") if split.valid + split.test > 1.0 => {
+            return Err(ArgumentError::InvalidNumber(String::from(
+                "--valid and --test add up to more than 1",
+            )))
+        }
+        Some("split") => Some(Command::Split(split)),
+        Some(other) => {
+            return Err(ArgumentError::UnknownOption(format!(
+                "{} is not a command",
+                other
+            )))
+        }
+    };
+    Ok((format, command))
 }
 
 impl Arguments {
-    fn new(image_dir: ReadDir, names: Vec<String>, names_dir: PathBuf) -> Arguments {
+    fn new(
+        image_dir: ReadDir,
+        names: Vec<String>,
+        names_file: PathBuf,
+        (format, command): (LabelFormat, Option<Command>),
+    ) -> Arguments {
         let images = image_dir
             .map(|r| r.expect("failed to read a directory entry"))
             .map(std::convert::TryInto::try_into)
@@ -51,10 +170,17 @@ impl Arguments {
             images.len() > 0,
             "the images directory must contain at least 1 image."
         );
+        let names_dir = names_file
+            .parent()
+            .expect("names file must have a parent directory")
+            .to_path_buf();
         Arguments {
             image_dir: images,
             names,
+            names_file,
             names_dir,
+            format,
+            command,
         }
     }
 }
@@ -67,9 +193,15 @@ impl Arguments {
 /// - if the first argument is not a directory
 /// - if the second argument is not a file
 /// - if the second arguments extension is not .names
+/// - if an optional argument is not one we know
 pub fn wrangle_args(args: Args) -> Result<Arguments, ArgumentError> {
-    let args = args.collect::<Vec<_>>();
-    if let [_, dir_path, names_path, _optional @ ..] = args.as_slice() {
+    let mut args = args.collect::<Vec<_>>();
+    let command = match args.get(1) {
+        Some(first) if Command::NAMES.contains(&first.as_str()) => Some(args.remove(1)),
+        _ => None,
+    };
+    if let [_, dir_path, names_path, optional @ ..] = args.as_slice() {
+        let options = parse_options(command.as_deref(), optional)?;
         let dir = Path::new(dir_path);
         let names = Path::new(names_path);
         if !dir.exists() {
@@ -97,9 +229,6 @@ pub fn wrangle_args(args: Args) -> Result<Arguments, ArgumentError> {
                 Ok(dir) => dir,
                 Err(err) => return Err(ArgumentError::ReadError(err.to_string())),
             };
-            let names_directory = names
-                .parent()
-                .expect("names file must have a parent directory");
             let names = match File::open(names) {
                 Ok(f) => match BufReader::new(f)
                     .lines()
@@ -113,7 +242,8 @@ pub fn wrangle_args(args: Args) -> Result<Arguments, ArgumentError> {
             Ok(Arguments::new(
                 images_directory,
                 names,
-                PathBuf::from(names_directory),
+                PathBuf::from(names_path),
+                options,
             ))
         } else {
             Err(ArgumentError::InvalidFileType(format!(
@@ -123,7 +253,8 @@ pub fn wrangle_args(args: Args) -> Result<Arguments, ArgumentError> {
         }
     } else {
         Err(ArgumentError::InvalidNumber(format!(
-            "expected arguments of the format <images directory> <names file> [optional args]. found {}: [\"{}\"]",
+            "expected arguments of the format [{}] <images directory> <names file> [optional args]. found {}: [\"{}\"]",
+            Command::NAMES.join("|"),
             args.len(),
             args.join("\",\"")
         )))
@@ -0,0 +1,39 @@
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+
+/// Replaces the contents of `path` with `contents` so that a crash or full disk leaves either
+/// the old file or the new one, never something in between.
+///
+/// The contents go to a temporary file in the same directory (a rename across file systems is
+/// not atomic), which is synced before being renamed over `path`.
+pub(crate) fn write(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let file_name = path.file_name().ok_or_else(|| {
+        std::io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} is not a file", path.display()),
+        )
+    })?;
+    let mut tmp_name = OsString::from(".");
+    tmp_name.push(file_name);
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+    let result = File::create(&tmp_path)
+        .and_then(|mut f| {
+            f.write_all(contents)?;
+            f.sync_all()
+        })
+        .and_then(|()| std::fs::rename(&tmp_path, path));
+    if result.is_err() {
+        // the original is untouched, don't leave half a file lying around next to it
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    result?;
+    // make the rename itself durable, not every platform lets you open a directory for this so
+    // failing here is not worth reporting
+    if let Some(dir) = path.parent().and_then(|dir| File::open(dir).ok()) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
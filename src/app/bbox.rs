@@ -5,7 +5,7 @@ use std::fmt::{Debug, Display, Formatter};
 use std::num::{ParseFloatError, ParseIntError};
 
 use eframe::egui::color::Hsva;
-use eframe::egui::{Align2, Color32, Painter, Pos2, Rect, Stroke, TextStyle, Vec2};
+use eframe::egui::{Align2, Color32, CursorIcon, Painter, Pos2, Rect, Stroke, TextStyle, Vec2};
 use rand::Rng;
 use rand_chacha::rand_core::SeedableRng;
 use rand_chacha::ChaCha8Rng;
@@ -88,6 +88,76 @@ pub struct BBox {
     pub height: f32,
     pub x: f32,
     pub y: f32,
+    /// pascal VOC flags, darknet files have nowhere to put them
+    pub difficult: bool,
+    pub truncated: bool,
+}
+
+/// one of the eight grab points drawn on the outline of the selected box
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Handle {
+    TopLeft,
+    Top,
+    TopRight,
+    Right,
+    BottomRight,
+    Bottom,
+    BottomLeft,
+    Left,
+}
+
+impl Handle {
+    const SIZE: f32 = 8.0;
+
+    // corners first so they win over edges on small boxes
+    const ALL: [Handle; 8] = [
+        Handle::TopLeft,
+        Handle::TopRight,
+        Handle::BottomRight,
+        Handle::BottomLeft,
+        Handle::Top,
+        Handle::Right,
+        Handle::Bottom,
+        Handle::Left,
+    ];
+
+    fn hit_rect(self, rect: Rect) -> Rect {
+        let x = match self {
+            Handle::TopLeft | Handle::Left | Handle::BottomLeft => rect.min.x,
+            Handle::Top | Handle::Bottom => rect.center().x,
+            Handle::TopRight | Handle::Right | Handle::BottomRight => rect.max.x,
+        };
+        let y = match self {
+            Handle::TopLeft | Handle::Top | Handle::TopRight => rect.min.y,
+            Handle::Left | Handle::Right => rect.center().y,
+            Handle::BottomLeft | Handle::Bottom | Handle::BottomRight => rect.max.y,
+        };
+        Rect::from_center_size(Pos2 { x, y }, Vec2::splat(Handle::SIZE))
+    }
+
+    /// moves the edges this handle is attached to by `diff`
+    fn drag(self, Rect { mut min, mut max }: Rect, diff: Pos2) -> (Pos2, Pos2) {
+        match self {
+            Handle::TopLeft | Handle::Left | Handle::BottomLeft => min.x += diff.x,
+            Handle::TopRight | Handle::Right | Handle::BottomRight => max.x += diff.x,
+            Handle::Top | Handle::Bottom => {}
+        }
+        match self {
+            Handle::TopLeft | Handle::Top | Handle::TopRight => min.y += diff.y,
+            Handle::BottomLeft | Handle::Bottom | Handle::BottomRight => max.y += diff.y,
+            Handle::Left | Handle::Right => {}
+        }
+        (min, max)
+    }
+
+    pub(crate) fn cursor_icon(self) -> CursorIcon {
+        match self {
+            Handle::TopLeft | Handle::BottomRight => CursorIcon::ResizeNwSe,
+            Handle::TopRight | Handle::BottomLeft => CursorIcon::ResizeNeSw,
+            Handle::Top | Handle::Bottom => CursorIcon::ResizeVertical,
+            Handle::Left | Handle::Right => CursorIcon::ResizeHorizontal,
+        }
+    }
 }
 
 impl BBox {
@@ -95,6 +165,28 @@ impl BBox {
         self.to_string()
     }
 
+    /// the same box with a different name, recoloured to match
+    pub fn with_name(&self, name: usize) -> BBox {
+        BBox {
+            color: Self::colour(name),
+            name,
+            ..self.clone()
+        }
+    }
+
+    /// this box with the VOC flags of `other`, for edits that build a new box from an old one
+    pub(crate) fn with_flags_of(self, other: &BBox) -> BBox {
+        BBox {
+            difficult: other.difficult,
+            truncated: other.truncated,
+            ..self
+        }
+    }
+
+    pub fn area(&self) -> f32 {
+        self.width * self.height
+    }
+
     pub fn is_larger(&self, other: &Self) -> bool {
         self.width > other.width && self.height > other.height
     }
@@ -117,6 +209,48 @@ impl BBox {
         )
     }
 
+    /// moves the box by `drag_diff` (in the same coordinates as `rect`), it stops at the edges of
+    /// the image so moving never changes its size.
+    pub fn moved_on_rect(&self, rect: Rect, drag_diff: Pos2) -> Result<BBox, BBoxError> {
+        // `max` before `min` rather than `clamp`, which panics on a box a hair wider than the image
+        let keep_inside = |center: f32, size: f32| center.max(size / 2.0).min(1.0 - size / 2.0);
+        BBox::new(
+            self.name,
+            self.width,
+            self.height,
+            keep_inside(self.x + drag_diff.x / rect.width(), self.width),
+            keep_inside(self.y + drag_diff.y / rect.height(), self.height),
+        )
+        .map(|bbox| bbox.with_flags_of(self))
+    }
+
+    /// drags the edges attached to `handle` by `drag_diff` (in the same coordinates as `rect`),
+    /// dragging past the opposite edge flips the box rather than erroring.
+    pub fn resized_on_rect(
+        &self,
+        rect: Rect,
+        handle: Handle,
+        drag_diff: Pos2,
+    ) -> Result<BBox, BBoxError> {
+        let (min, max) = handle.drag(self.with_respect_to(rect), drag_diff);
+        BBox::from_two_points(
+            self.name,
+            min - rect.min.to_vec2(),
+            max - rect.min.to_vec2(),
+            rect.size(),
+        )
+        .map(|bbox| bbox.with_flags_of(self))
+    }
+
+    /// the handle of this box under `pos`, if any
+    pub(crate) fn handle_at(&self, rect: Rect, pos: Pos2) -> Option<Handle> {
+        let rect = self.with_respect_to(rect);
+        Handle::ALL
+            .iter()
+            .copied()
+            .find(|handle| handle.hit_rect(rect).contains(pos))
+    }
+
     pub(crate) fn from_two_points_relative(
         name: usize,
         Pos2 {
@@ -205,20 +339,38 @@ impl BBox {
 }
 
 impl BBox {
-    pub(crate) fn draw(&self, painter: &mut Painter, alpha: u8, selected: bool) -> Rect {
+    pub(crate) fn draw(
+        &self,
+        painter: &mut Painter,
+        image_rect: Rect,
+        alpha: u8,
+        selected: bool,
+    ) -> Rect {
         if selected {
             let color = Color32::from_white_alpha(255);
-            let rect = self.with_respect_to(painter.clip_rect());
+            let rect = self.with_respect_to(image_rect);
             BBox::draw_colored_box_outline(painter, color, rect, 2.0);
             rect
         } else {
             let color = self.color_w_alpha(alpha);
-            let rect = self.with_respect_to(painter.clip_rect());
+            let rect = self.with_respect_to(image_rect);
             BBox::draw_colored_box_outline(painter, color, rect, 1.0);
             rect
         }
     }
 
+    pub(crate) fn draw_handles(&self, painter: &mut Painter, image_rect: Rect) {
+        let rect = self.with_respect_to(image_rect);
+        for handle in &Handle::ALL {
+            painter.rect(
+                handle.hit_rect(rect),
+                0.0,
+                Color32::WHITE,
+                Stroke::new(1.0_f32, Color32::BLACK),
+            );
+        }
+    }
+
     fn color_w_alpha(&self, alpha: u8) -> Color32 {
         let [r, g, b] = self.color;
         Color32::from_rgba_premultiplied(r, g, b, alpha)
@@ -341,6 +493,8 @@ impl BBox {
                 height,
                 x,
                 y,
+                difficult: false,
+                truncated: false,
             })
         }
     }
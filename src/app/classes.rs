@@ -0,0 +1,328 @@
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::app::atomic_file;
+use crate::app::bbox::BBox;
+use crate::app::image_file::{LabelFormat, LabelStorage};
+use crate::app::images::Images;
+use crate::app::voc;
+
+/// A change to the class list. Every box in every label file is renumbered to match.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ClassEdit {
+    Add(String),
+    Rename(usize, String),
+    /// take the class at `from` out and put it back in at `to`
+    Move {
+        from: usize,
+        to: usize,
+    },
+    /// relabel every box of `from` as `into`, then delete `from`
+    Merge {
+        from: usize,
+        into: usize,
+    },
+    /// delete the class and every box of it
+    Delete(usize),
+}
+
+#[derive(Debug)]
+pub enum ClassError {
+    Io(std::io::Error),
+    Invalid(String),
+}
+
+impl Display for ClassError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ClassError::Io(err) => write!(f, "Io {}", err),
+            ClassError::Invalid(explanation) => write!(f, "Invalid {}", explanation),
+        }
+    }
+}
+
+impl From<std::io::Error> for ClassError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl Error for ClassError {}
+
+impl ClassEdit {
+    /// the class list after this edit
+    ///
+    /// # Errors
+    /// if a class does not exist, or a name is empty or already taken
+    pub fn names_after(&self, names: &[String]) -> Result<Vec<String>, ClassError> {
+        let check_index = |i: usize| {
+            if i < names.len() {
+                Ok(())
+            } else {
+                Err(ClassError::Invalid(format!("there is no class {}", i)))
+            }
+        };
+        let check_name = |name: &str| {
+            if name.trim().is_empty() {
+                Err(ClassError::Invalid(String::from("a class needs a name")))
+            } else if names.iter().any(|it| it == name) {
+                Err(ClassError::Invalid(format!("{} is already a class", name)))
+            } else {
+                Ok(())
+            }
+        };
+        let mut after = names.to_vec();
+        match self {
+            ClassEdit::Add(name) => {
+                check_name(name)?;
+                after.push(name.clone());
+            }
+            ClassEdit::Rename(i, name) => {
+                check_index(*i)?;
+                check_name(name)?;
+                after[*i].clone_from(name);
+            }
+            ClassEdit::Move { from, to } => {
+                check_index(*from)?;
+                check_index(*to)?;
+                let name = after.remove(*from);
+                after.insert(*to, name);
+            }
+            ClassEdit::Merge { from, into } => {
+                check_index(*from)?;
+                check_index(*into)?;
+                if from == into {
+                    return Err(ClassError::Invalid(String::from(
+                        "cannot merge a class into itself",
+                    )));
+                }
+                after.remove(*from);
+            }
+            ClassEdit::Delete(i) => {
+                check_index(*i)?;
+                if names.len() == 1 {
+                    return Err(ClassError::Invalid(String::from(
+                        "the last class cannot be deleted",
+                    )));
+                }
+                after.remove(*i);
+            }
+        }
+        Ok(after)
+    }
+
+    /// what the edit does, to confirm it before anything is rewritten
+    pub(crate) fn describe(&self, names: &[String]) -> String {
+        let name = |i: usize| names.get(i).map_or("?", String::as_str);
+        match self {
+            ClassEdit::Add(new) => format!("add {}", new),
+            ClassEdit::Rename(i, new) => format!("rename {} to {}", name(*i), new),
+            ClassEdit::Move { from, to } => format!("move {} to {}", name(*from), to),
+            ClassEdit::Merge { from, into } => format!(
+                "relabel every {} box as {} and delete {}",
+                name(*from),
+                name(*into),
+                name(*from)
+            ),
+            ClassEdit::Delete(i) => format!("delete {} and every box of it", name(*i)),
+        }
+    }
+
+    /// the index a box of class `name` has after this edit, `None` if the box is deleted
+    pub fn remap(&self, name: usize) -> Option<usize> {
+        // the indices of everything after a removed class move down one
+        let removed = |removed: usize, name: usize| {
+            if name > removed {
+                name - 1
+            } else {
+                name
+            }
+        };
+        match *self {
+            ClassEdit::Add(_) | ClassEdit::Rename(..) => Some(name),
+            ClassEdit::Move { from, to } => Some(if name == from {
+                to
+            } else if from < to && (from..=to).contains(&name) {
+                name - 1
+            } else if to < from && (to..from).contains(&name) {
+                name + 1
+            } else {
+                name
+            }),
+            ClassEdit::Merge { from, into } => {
+                Some(removed(from, if name == from { into } else { name }))
+            }
+            ClassEdit::Delete(deleted) => (name != deleted).then(|| removed(deleted, name)),
+        }
+    }
+
+    /// renumbers `boxes` with `remap`, and recolours them to match, removing the ones it deletes
+    pub(crate) fn remap_boxes(&self, boxes: &mut Vec<BBox>) {
+        boxes.retain_mut(|bbox| match self.remap(bbox.name) {
+            Some(name) => {
+                *bbox = bbox.with_name(name);
+                true
+            }
+            None => false,
+        });
+    }
+}
+
+/// Applies `edit` to every label file of `images` and to the names file at `names_file`,
+/// returning the new class list and a summary.
+///
+/// Only the class of each box is touched: the first column of darknet lines and the name of VOC
+/// objects. Lines and objects that are not of a known class are copied as they are, so a class
+/// edit never fixes, clamps or drops anything else in a label file.
+///
+/// Every label file is read before any is written, and the ones already written are put back if
+/// writing another fails, so the label files are never left numbered for two different class
+/// lists. The names file is written last.
+///
+/// # Errors
+/// if the edit is invalid, a label file cannot be read, or a file cannot be written
+pub fn apply(
+    edit: &ClassEdit,
+    images: &Images,
+    storage: LabelStorage<'_>,
+    names_file: &Path,
+) -> Result<(Vec<String>, String), ClassError> {
+    let names = edit.names_after(storage.names)?;
+    let mut rewrites = Vec::new();
+    let mut removed_boxes = 0;
+    for image in images.as_slice() {
+        if !image.img.has_label_file(storage.format) {
+            continue;
+        }
+        let label_path = image.img.label_path(storage.format);
+        let original = fs::read(&label_path)?;
+        let (contents, removed) = match storage.format {
+            LabelFormat::Darknet => remap_darknet(&original, edit, storage.names.len()),
+            LabelFormat::Voc => voc::rename_objects(&original, |name| {
+                match storage.names.iter().position(|it| it == name) {
+                    Some(i) => edit.remap(i).map(|i| names[i].clone()),
+                    None => Some(name.to_string()),
+                }
+            })
+            .map_err(|err| ClassError::Invalid(format!("{}: {}", label_path.display(), err)))?,
+        };
+        if contents != original {
+            removed_boxes += removed;
+            rewrites.push((label_path, original, contents));
+        }
+    }
+    let mut written: Vec<(PathBuf, Vec<u8>)> = Vec::new();
+    for (label_path, original, contents) in rewrites {
+        if let Err(err) = atomic_file::write(&label_path, &contents) {
+            restore(&written);
+            return Err(err.into());
+        }
+        written.push((label_path, original));
+    }
+    if let Err(err) = atomic_file::write(names_file, format!("{}\n", names.join("\n")).as_bytes()) {
+        restore(&written);
+        return Err(err.into());
+    }
+    Ok((
+        names,
+        format!(
+            "rewrote {} label files, removed {} boxes",
+            written.len(),
+            removed_boxes
+        ),
+    ))
+}
+
+/// `contents` of a darknet label file with the class of each line remapped by `edit`, and how
+/// many lines were removed. Lines that do not start with one of the `class_count` classes are
+/// copied byte for byte, and so is everything after the class.
+fn remap_darknet(contents: &[u8], edit: &ClassEdit, class_count: usize) -> (Vec<u8>, usize) {
+    let mut remapped = Vec::with_capacity(contents.len());
+    let mut removed = 0;
+    for line in contents.split_inclusive(|byte| *byte == b'\n') {
+        let end = line
+            .iter()
+            .position(u8::is_ascii_whitespace)
+            .unwrap_or(line.len());
+        let (class, rest) = line.split_at(end);
+        let class = std::str::from_utf8(class)
+            .ok()
+            .and_then(|class| class.parse::<usize>().ok())
+            .filter(|class| *class < class_count);
+        match class.map(|class| edit.remap(class)) {
+            Some(Some(class)) => {
+                remapped.extend_from_slice(class.to_string().as_bytes());
+                remapped.extend_from_slice(rest);
+            }
+            Some(None) => removed += 1,
+            None => remapped.extend_from_slice(line),
+        }
+    }
+    (remapped, removed)
+}
+
+/// puts back the label files an edit has already rewritten
+fn restore(written: &[(PathBuf, Vec<u8>)]) {
+    for (path, contents) in written.iter().rev() {
+        if let Err(err) = atomic_file::write(path, contents) {
+            println!("could not put {} back: {}", path.display(), err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remap_moves_merges_and_deletes() {
+        let moved = ClassEdit::Move { from: 0, to: 2 };
+        assert_eq!(
+            (0..4).map(|name| moved.remap(name)).collect::<Vec<_>>(),
+            vec![Some(2), Some(0), Some(1), Some(3)]
+        );
+        let merged = ClassEdit::Merge { from: 1, into: 2 };
+        assert_eq!(
+            (0..4).map(|name| merged.remap(name)).collect::<Vec<_>>(),
+            vec![Some(0), Some(1), Some(1), Some(2)]
+        );
+        let deleted = ClassEdit::Delete(1);
+        assert_eq!(
+            (0..4).map(|name| deleted.remap(name)).collect::<Vec<_>>(),
+            vec![Some(0), None, Some(1), Some(2)]
+        );
+        assert_eq!(ClassEdit::Rename(0, String::from("a")).remap(3), Some(3));
+    }
+
+    #[test]
+    fn remap_darknet_only_touches_the_class_column() {
+        let contents =
+            b"0 0.5 0.5 0.2 0.2\r\n1 0.50000001 0.5 1.3 0.2\nnot a box\n7 0.1 0.1 0.1 0.1\n2 0.1 0.1 0.1 0.1";
+        let (remapped, removed) = remap_darknet(contents, &ClassEdit::Delete(0), 3);
+        assert_eq!(removed, 1);
+        assert_eq!(
+            String::from_utf8(remapped).unwrap(),
+            "0 0.50000001 0.5 1.3 0.2\nnot a box\n7 0.1 0.1 0.1 0.1\n1 0.1 0.1 0.1 0.1"
+        );
+    }
+
+    #[test]
+    fn remap_darknet_leaves_an_unchanged_file_alone() {
+        let contents = b"0 0.5 0.5 0.2 0.2\n1 0.5 0.5 0.2 0.2\n";
+        let edit = ClassEdit::Add(String::from("new"));
+        assert_eq!(remap_darknet(contents, &edit, 2), (contents.to_vec(), 0));
+    }
+
+    #[test]
+    fn remap_boxes_recolours_and_drops_deleted_classes() {
+        let mut boxes = vec![
+            BBox::new(0, 0.2, 0.2, 0.5, 0.5).unwrap(),
+            BBox::new(1, 0.2, 0.2, 0.5, 0.5).unwrap(),
+        ];
+        ClassEdit::Delete(0).remap_boxes(&mut boxes);
+        assert_eq!(boxes, vec![BBox::new(0, 0.2, 0.2, 0.5, 0.5).unwrap()]);
+    }
+}
@@ -0,0 +1,277 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use image::ImageError;
+
+use crate::app::atomic_file;
+use crate::app::bbox::{BBox, BBoxError};
+use crate::app::image_file::LabelStorage;
+use crate::app::images::Images;
+
+/// the subset of a COCO `instances.json` we read and write
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Coco {
+    images: Vec<CocoImage>,
+    annotations: Vec<CocoAnnotation>,
+    categories: Vec<CocoCategory>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CocoImage {
+    id: u64,
+    file_name: String,
+    width: u32,
+    height: u32,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CocoAnnotation {
+    #[serde(default)]
+    id: u64,
+    image_id: u64,
+    category_id: u64,
+    /// top left x, top left y, width, height in pixels
+    bbox: [f32; 4],
+    #[serde(default)]
+    area: f32,
+    #[serde(default)]
+    iscrowd: u8,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CocoCategory {
+    id: u64,
+    name: String,
+}
+
+#[derive(Debug)]
+pub enum CocoError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Image(ImageError),
+}
+
+impl Display for CocoError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            CocoError::Io(err) => write!(f, "Io {}", err),
+            CocoError::Json(err) => write!(f, "Json {}", err),
+            CocoError::Image(err) => write!(f, "Image {}", err),
+        }
+    }
+}
+
+impl From<std::io::Error> for CocoError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for CocoError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Json(err)
+    }
+}
+
+impl From<ImageError> for CocoError {
+    fn from(err: ImageError) -> Self {
+        Self::Image(err)
+    }
+}
+
+impl Error for CocoError {}
+
+/// Writes the labels of every image to a COCO file at `path`.
+///
+/// Categories are numbered from 1 in the order of the names file, boxes are in pixels of the grid
+/// the labels are stored against.
+///
+/// Images that cannot be read for their size are left out and listed in the returned summary.
+///
+/// # Errors
+/// if `path` cannot be written.
+pub fn export(
+    images: &Images,
+    storage: LabelStorage<'_>,
+    path: &Path,
+) -> Result<String, CocoError> {
+    let mut coco = Coco {
+        images: Vec::new(),
+        annotations: Vec::new(),
+        categories: storage
+            .names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| CocoCategory {
+                id: i as u64 + 1,
+                name: name.clone(),
+            })
+            .collect(),
+    };
+    let mut skipped = Vec::new();
+    for (i, image) in images.as_slice().iter().enumerate() {
+        let image_id = i as u64 + 1;
+        let (width, height) = match image.img.dimensions(storage.grid) {
+            Ok(dimensions) => dimensions,
+            Err(err) => {
+                skipped.push(format!("{}: {}", image.img.file_name(), err));
+                continue;
+            }
+        };
+        #[allow(clippy::cast_precision_loss)]
+        let (w, h) = (width as f32, height as f32);
+        for bbox in image.img.load_stored_labels(storage) {
+            let (box_w, box_h) = (bbox.width * w, bbox.height * h);
+            coco.annotations.push(CocoAnnotation {
+                id: coco.annotations.len() as u64 + 1,
+                image_id,
+                category_id: bbox.name as u64 + 1,
+                bbox: [
+                    bbox.x * w - box_w / 2.0,
+                    bbox.y * h - box_h / 2.0,
+                    box_w,
+                    box_h,
+                ],
+                area: box_w * box_h,
+                iscrowd: 0,
+            });
+        }
+        coco.images.push(CocoImage {
+            id: image_id,
+            file_name: image.img.file_name(),
+            width,
+            height,
+        });
+    }
+    atomic_file::write(path, &serde_json::to_vec(&coco)?)?;
+    let mut summary = format!(
+        "exported {} boxes on {} images to {}",
+        coco.annotations.len(),
+        coco.images.len(),
+        path.display()
+    );
+    for skip in skipped {
+        summary = format!("{}\nskipped {}", summary, skip);
+    }
+    Ok(summary)
+}
+
+/// Replaces the labels of every image in the COCO file at `path` with the boxes it contains.
+///
+/// Images are matched by file name and categories by name, anything that does not match is
+/// skipped and listed in the returned summary. Images that are not in the file, or have no boxes
+/// in it, are left alone so unvisited images stay unvisited. Boxes are converted with the size of
+/// the image on disk rather than the size the file claims.
+///
+/// # Errors
+/// if `path` cannot be read or parsed, or a label file cannot be written.
+pub fn import(
+    images: &Images,
+    storage: LabelStorage<'_>,
+    path: &Path,
+) -> Result<String, CocoError> {
+    let coco: Coco = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+    let categories = coco
+        .categories
+        .iter()
+        .map(|category| {
+            (
+                category.id,
+                (
+                    category.name.as_str(),
+                    storage.names.iter().position(|name| *name == category.name),
+                ),
+            )
+        })
+        .collect::<BTreeMap<_, _>>();
+    let mut boxes = coco
+        .images
+        .iter()
+        .map(|image| (image.id, Vec::new()))
+        .collect::<BTreeMap<_, _>>();
+    let mut skipped = Vec::new();
+    for annotation in &coco.annotations {
+        let name = match categories.get(&annotation.category_id) {
+            Some((_, Some(name))) => *name,
+            Some((category, None)) => {
+                skipped.push(format!("category {} is not in the names file", category));
+                continue;
+            }
+            None => {
+                skipped.push(format!("unknown category id {}", annotation.category_id));
+                continue;
+            }
+        };
+        match boxes.get_mut(&annotation.image_id) {
+            Some(image_boxes) => image_boxes.push((name, annotation.bbox)),
+            None => skipped.push(format!("unknown image id {}", annotation.image_id)),
+        }
+    }
+    let mut imported = (0, 0);
+    for image in &coco.images {
+        let file_name = Path::new(&image.file_name)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let image_file = images
+            .as_slice()
+            .iter()
+            .find(|it| it.img.file_name() == file_name);
+        let image_file = if let Some(it) = image_file {
+            &it.img
+        } else {
+            skipped.push(format!(
+                "{} is not in the images directory",
+                image.file_name
+            ));
+            continue;
+        };
+        if boxes[&image.id].is_empty() {
+            continue;
+        }
+        let (w, h) = match image_file.dimensions(storage.grid) {
+            #[allow(clippy::cast_precision_loss)]
+            Ok((w, h)) => (w as f32, h as f32),
+            Err(err) => {
+                skipped.push(format!("{}: {}", image.file_name, err));
+                continue;
+            }
+        };
+        let labels = boxes[&image.id]
+            .iter()
+            .map(|&(name, [x, y, box_w, box_h])| {
+                BBox::new(
+                    name,
+                    box_w / w,
+                    box_h / h,
+                    (x + box_w / 2.0) / w,
+                    (y + box_h / 2.0) / h,
+                )
+            })
+            .filter_map(|bbox: Result<BBox, BBoxError>| match bbox {
+                Ok(bbox) => Some(bbox),
+                Err(err) => {
+                    skipped.push(format!("box on {}: {}", image.file_name, err));
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        image_file.save_stored_labels(&labels, storage)?;
+        imported = (imported.0 + 1, imported.1 + labels.len());
+    }
+    let mut summary = format!(
+        "imported {} boxes on {} images from {}",
+        imported.1,
+        imported.0,
+        path.display()
+    );
+    for skip in skipped {
+        summary = format!("{}\nskipped {}", summary, skip);
+    }
+    Ok(summary)
+}
@@ -0,0 +1,126 @@
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+
+use crate::app::arguments::Arguments;
+use crate::app::coco;
+use crate::app::coco::CocoError;
+use crate::app::image_file::{LabelFormat, LabelStorage};
+use crate::app::settings::Settings;
+use crate::app::split::SplitOptions;
+use crate::app::{split, stats, validate};
+
+/// Something to do with the dataset instead of opening the gui, the first argument picks it.
+pub enum Command {
+    /// check every label file and list what is wrong with them, as JSON if `json`
+    Validate {
+        json: bool,
+    },
+    /// print the same counts as the stats page
+    Stats,
+    Convert(Conversion),
+    /// write image lists for training next to the names file, see [`split::split`]
+    Split(SplitOptions),
+}
+
+pub enum Conversion {
+    ImportCoco(PathBuf),
+    ExportCoco(PathBuf),
+    /// rewrite every label file in another format
+    Labels(LabelFormat),
+}
+
+#[derive(Debug)]
+pub enum CommandError {
+    Io(std::io::Error),
+    Coco(CocoError),
+    /// the command ran but found problems, this is the report
+    Invalid(String),
+}
+
+impl Display for CommandError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::Io(err) => write!(f, "Io {}", err),
+            CommandError::Coco(err) => write!(f, "Coco {}", err),
+            CommandError::Invalid(report) => write!(f, "{}", report),
+        }
+    }
+}
+
+impl From<std::io::Error> for CommandError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<CocoError> for CommandError {
+    fn from(err: CocoError) -> Self {
+        Self::Coco(err)
+    }
+}
+
+impl Error for CommandError {}
+
+impl Command {
+    pub(crate) const NAMES: [&'static str; 4] = ["validate", "stats", "convert", "split"];
+
+    /// runs the command and returns what it has to say
+    ///
+    /// # Errors
+    /// if a file cannot be read or written, or `validate` finds problems
+    pub fn run(&self, args: &Arguments) -> Result<String, CommandError> {
+        let storage = LabelStorage {
+            format: args.format,
+            grid: Settings::from_file().unwrap_or_default().label_grid,
+            names: &args.names,
+        };
+        match self {
+            Command::Validate { json } => {
+                let problems = validate::validate(&args.image_dir, storage);
+                let report = if *json {
+                    serde_json::to_string_pretty(&problems).map_err(std::io::Error::from)?
+                } else if problems.is_empty() {
+                    String::from("no problems found")
+                } else {
+                    validate::report(&problems)
+                };
+                if problems.is_empty() {
+                    Ok(report)
+                } else {
+                    Err(CommandError::Invalid(report))
+                }
+            }
+            Command::Stats => Ok(stats::report(&args.image_dir, storage)),
+            Command::Convert(Conversion::ImportCoco(path)) => {
+                Ok(coco::import(&args.image_dir, storage, path)?)
+            }
+            Command::Convert(Conversion::ExportCoco(path)) => {
+                Ok(coco::export(&args.image_dir, storage, path)?)
+            }
+            Command::Convert(Conversion::Labels(format)) => {
+                let target = LabelStorage {
+                    format: *format,
+                    ..storage
+                };
+                let mut converted = 0;
+                for image in args.image_dir.as_slice() {
+                    if image.img.has_label_file(storage.format) {
+                        let labels = image.img.load_stored_labels(storage);
+                        image.img.save_stored_labels(&labels, target)?;
+                        converted += 1;
+                    }
+                }
+                Ok(format!("converted {} label files", converted))
+            }
+            Command::Split(options) => Ok(split::split(
+                &args.image_dir,
+                storage,
+                &args.names_file,
+                &args.names_dir,
+                options,
+            )?),
+        }
+    }
+}
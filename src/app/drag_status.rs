@@ -1,14 +1,36 @@
 use eframe::egui::Pos2;
 
+use crate::app::bbox::Handle;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) enum DragKind {
+    /// drawing a new box from the drag start
+    New,
+    /// translating the box at this index of `current_boxes`
+    Move(usize),
+    /// dragging one of the handles of the box at this index of `current_boxes`
+    Resize(usize, Handle),
+}
+
 pub struct DragStatus {
     pub(crate) drag_start: Option<Pos2>,
     pub(crate) drag_diff: Option<Pos2>,
+    pub(crate) kind: DragKind,
 }
 
 impl DragStatus {
-    pub(crate) fn start(&mut self, pos: Pos2) {
+    pub(crate) fn start(&mut self, pos: Pos2, kind: DragKind) {
         self.drag_start = Some(pos);
         self.drag_diff = Some(Pos2::ZERO);
+        self.kind = kind;
+    }
+
+    /// the index of the box being moved or resized
+    pub(crate) fn edited_box(&self) -> Option<usize> {
+        match (self.drag_diff, self.kind) {
+            (Some(_), DragKind::Move(index) | DragKind::Resize(index, _)) => Some(index),
+            _ => None,
+        }
     }
 }
 
@@ -16,6 +38,7 @@ impl DragStatus {
     pub(crate) fn clear(&mut self) {
         self.drag_diff = None;
         self.drag_start = None;
+        self.kind = DragKind::New;
     }
 }
 
@@ -24,6 +47,7 @@ impl DragStatus {
         DragStatus {
             drag_start: None,
             drag_diff: None,
+            kind: DragKind::New,
         }
     }
 }
@@ -0,0 +1,74 @@
+use crate::app::image_file::LabelFormat;
+use crate::app::images::Image;
+use crate::app::stats::DatasetStats;
+
+/// Which images Next and Prev stop at.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum NavFilter {
+    All,
+    /// images without a label file
+    Unvisited,
+    /// images without any boxes, reviewed or not
+    Unlabeled,
+    /// images in any mark category
+    Marked,
+    /// images with at least one box of this name
+    Containing(usize),
+    MoreBoxesThan(usize),
+    FewerBoxesThan(usize),
+}
+
+impl NavFilter {
+    /// one of each kind of filter, for picking from a list
+    pub(crate) fn kinds(name: usize, boxes: usize) -> [NavFilter; 7] {
+        [
+            NavFilter::All,
+            NavFilter::Unvisited,
+            NavFilter::Unlabeled,
+            NavFilter::Marked,
+            NavFilter::Containing(name),
+            NavFilter::MoreBoxesThan(boxes),
+            NavFilter::FewerBoxesThan(boxes),
+        ]
+    }
+
+    pub(crate) fn same_kind(self, other: NavFilter) -> bool {
+        std::mem::discriminant(&self) == std::mem::discriminant(&other)
+    }
+
+    pub(crate) fn describe(self, names: &[String]) -> String {
+        match self {
+            NavFilter::All => String::from("all images"),
+            NavFilter::Unvisited => String::from("unvisited"),
+            NavFilter::Unlabeled => String::from("no boxes"),
+            NavFilter::Marked => String::from("marked"),
+            NavFilter::Containing(name) => {
+                format!("containing {}", names.get(name).map_or("?", String::as_str))
+            }
+            NavFilter::MoreBoxesThan(n) => format!("more than {} boxes", n),
+            NavFilter::FewerBoxesThan(n) => format!("fewer than {} boxes", n),
+        }
+    }
+
+    /// Whether `image`, the image at `index`, passes. Filters that look at boxes go by what
+    /// `stats` last read from its label file, so nothing is read here, and are `None` if `stats`
+    /// has not read it yet.
+    pub(crate) fn matches(
+        self,
+        index: usize,
+        image: &Image,
+        format: LabelFormat,
+        stats: &DatasetStats,
+    ) -> Option<bool> {
+        let names = || stats.box_names(index);
+        Some(match self {
+            NavFilter::All => true,
+            NavFilter::Unvisited => !image.img.has_label_file(format),
+            NavFilter::Unlabeled => names()?.next().is_none(),
+            NavFilter::Marked => !image.marks.is_empty(),
+            NavFilter::Containing(name) => names()?.any(|it| it == name),
+            NavFilter::MoreBoxesThan(n) => names()?.count() > n,
+            NavFilter::FewerBoxesThan(n) => names()?.count() < n,
+        })
+    }
+}
@@ -0,0 +1,48 @@
+use std::collections::BTreeMap;
+
+use crate::app::bbox::BBox;
+
+/// how many edits are remembered for each image
+const MAX_DEPTH: usize = 100;
+
+#[derive(Default)]
+struct Stacks {
+    undo: Vec<Vec<BBox>>,
+    redo: Vec<Vec<BBox>>,
+}
+
+/// Snapshots of the boxes on each image before every edit.
+///
+/// Keyed by image index rather than tied to `current_boxes` so going back to an image still lets
+/// you undo what you did there.
+#[derive(Default)]
+pub struct History(BTreeMap<usize, Stacks>);
+
+impl History {
+    /// remembers `before` as the state of `image` prior to an edit, this forgets anything that
+    /// could have been redone.
+    pub(crate) fn record(&mut self, image: usize, before: Vec<BBox>) {
+        let stacks = self.0.entry(image).or_default();
+        stacks.undo.push(before);
+        if stacks.undo.len() > MAX_DEPTH {
+            stacks.undo.remove(0);
+        }
+        stacks.redo.clear();
+    }
+
+    /// the boxes `image` had before the last edit, `current` becomes redoable.
+    pub(crate) fn undo(&mut self, image: usize, current: &[BBox]) -> Option<Vec<BBox>> {
+        let stacks = self.0.get_mut(&image)?;
+        let prev = stacks.undo.pop()?;
+        stacks.redo.push(current.to_vec());
+        Some(prev)
+    }
+
+    /// the boxes `image` had before the last undo, `current` becomes undoable again.
+    pub(crate) fn redo(&mut self, image: usize, current: &[BBox]) -> Option<Vec<BBox>> {
+        let stacks = self.0.get_mut(&image)?;
+        let next = stacks.redo.pop()?;
+        stacks.undo.push(current.to_vec());
+        Some(next)
+    }
+}
@@ -7,9 +7,9 @@ use std::time::Duration;
 
 use crossbeam::channel::TryRecvError;
 use crossbeam::channel::{Receiver, Sender};
-use eframe::egui::{Color32, Vec2};
+use eframe::egui::{Color32, Pos2, Rect, Vec2};
 use image::imageops::FilterType;
-use image::{GenericImageView, ImageError};
+use image::{DynamicImage, GenericImageView, ImageError};
 
 use crate::app::image_file;
 use crate::app::image_file::ImageFile;
@@ -17,6 +17,12 @@ use crate::app::image_file::ImageFile;
 type PixelsMessage = Result<(ImageLookup, ImageData, Option<Color32>), ImageParseError>;
 type ImageMessage = (ImageLookup, PathBuf);
 
+/// all of an image in normalized image coordinates
+const WHOLE_IMAGE: Rect = Rect {
+    min: Pos2 { x: 0.0, y: 0.0 },
+    max: Pos2 { x: 1.0, y: 1.0 },
+};
+
 pub struct ImageCache {
     size: Arc<Mutex<Vec2>>,
     cache: BTreeMap<ImageLookup, (ImageData, Option<Color32>)>,
@@ -28,9 +34,62 @@ pub struct ImageCache {
 pub struct ImageData {
     size: (usize, usize),
     pub(crate) data: Vec<Color32>,
+    /// the part of the image the pixels cover, in normalized image coordinates
+    pub(crate) bounds: Rect,
 }
 
-impl ImageData {}
+impl ImageData {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn new(img: &DynamicImage, Vec2 { x: w, y: h }: Vec2) -> ImageData {
+        // converting to 8 bit rgba handles grayscale, 16 bit and missing alpha channels for us
+        let resized = img
+            .resize(w as u32, h as u32, FilterType::Nearest)
+            .to_rgba8();
+        let pixels = resized
+            .pixels()
+            .map(|p| Color32::from_rgba_unmultiplied(p.0[0], p.0[1], p.0[2], p.0[3]))
+            .collect::<Vec<_>>();
+        ImageData {
+            size: (
+                resized
+                    .dimensions()
+                    .0
+                    .try_into()
+                    .expect("dimensions.x did not fit into a usize"),
+                resized
+                    .dimensions()
+                    .1
+                    .try_into()
+                    .expect("dimensions.x did not fit into a usize"),
+            ),
+            data: pixels,
+            bounds: WHOLE_IMAGE,
+        }
+    }
+
+    fn average_color(&self) -> Option<Color32> {
+        let (r, g, b, a) = self.data.iter().map(Color32::to_tuple).fold(
+            (0_u128, 0_u128, 0_u128, 0_u128),
+            |(ra, ba, ga, aa), (r, g, b, a)| {
+                (
+                    ra + r as u128,
+                    ba + b as u128,
+                    ga + g as u128,
+                    aa + a as u128,
+                )
+            },
+        );
+        let size = self.data.len() as u128;
+        (size != 0).then(|| {
+            Color32::from_rgba_premultiplied(
+                (r / size) as u8,
+                (b / size) as u8,
+                (g / size) as u8,
+                (a / size) as u8,
+            )
+        })
+    }
+}
 
 impl ImageData {
     #[allow(clippy::cast_precision_loss)]
@@ -55,6 +114,59 @@ enum ImageParseError {
 #[derive(Eq, PartialEq, Hash, Debug, Copy, Clone, Ord, PartialOrd)]
 pub struct ImageLookup {
     pub(crate) index: usize,
+    /// only decode this part of the image, stretched to the full size
+    pub(crate) region: Option<Region>,
+}
+
+/// A part of an image in ten-thousandths of its width and height, stored as integers so it can
+/// be used as a cache key.
+#[derive(Eq, PartialEq, Hash, Debug, Copy, Clone, Ord, PartialOrd)]
+pub struct Region {
+    min: (u16, u16),
+    max: (u16, u16),
+}
+
+impl Region {
+    const SCALE: f32 = 10_000.0;
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub(crate) fn from_rect(rect: Rect) -> Region {
+        let scale = |v: f32| (v.clamp(0.0, 1.0) * Self::SCALE).round() as u16;
+        Region {
+            min: (scale(rect.min.x), scale(rect.min.y)),
+            max: (scale(rect.max.x), scale(rect.max.y)),
+        }
+    }
+
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    /// The region grown out to whole pixels, and the part of the image that ended up being
+    /// cropped. Drawing the crop over exactly that part keeps it lined up with the boxes.
+    fn crop(self, img: &DynamicImage) -> (DynamicImage, Rect) {
+        let (w, h) = img.dimensions();
+        let pixel = |v: u16, size: u32| f32::from(v) / Self::SCALE * size as f32;
+        let min_x = (pixel(self.min.0, w).floor() as u32).min(w - 1);
+        let min_y = (pixel(self.min.1, h).floor() as u32).min(h - 1);
+        let max_x = (pixel(self.max.0, w).ceil() as u32).clamp(min_x + 1, w);
+        let max_y = (pixel(self.max.1, h).ceil() as u32).clamp(min_y + 1, h);
+        let bounds = Rect::from_min_max(
+            Pos2 {
+                x: min_x as f32 / w as f32,
+                y: min_y as f32 / h as f32,
+            },
+            Pos2 {
+                x: max_x as f32 / w as f32,
+                y: max_y as f32 / h as f32,
+            },
+        );
+        (
+            img.crop_imm(min_x, min_y, max_x - min_x, max_y - min_y),
+            bounds,
+        )
+    }
 }
 
 impl ImageCache {
@@ -73,51 +185,16 @@ impl ImageCache {
                         Ok((lookup, file)) => match ImageFile::new(file) {
                             Ok(img) => match img.as_image() {
                                 Ok(img) => {
-                                    let Vec2 { x: w, y: h } =
-                                        { *arc_clone.lock().expect("lock was poisoned") };
-                                    let resized =
-                                        img.resize(w as u32, h as u32, FilterType::Nearest);
-                                    let pixels = resized
-                                        .pixels()
-                                        .map(|(.., p)| {
-                                            Color32::from_rgba_premultiplied(
-                                                p.0[0], p.0[1], p.0[2], p.0[3],
-                                            )
-                                        })
-                                        .collect::<Vec<_>>();
-                                    let data =
-                                        ImageData {
-                                            size: (
-                                                resized.dimensions().0.try_into().expect(
-                                                    "dimensions.x did not fit into a usize",
-                                                ),
-                                                resized.dimensions().1.try_into().expect(
-                                                    "dimensions.x did not fit into a usize",
-                                                ),
-                                            ),
-                                            data: pixels,
-                                        };
-                                    let (r, g, b, a) =
-                                        data.data.iter().map(Color32::to_tuple).fold(
-                                            (0_u128, 0_u128, 0_u128, 0_u128),
-                                            |(ra, ba, ga, aa), (r, g, b, a)| {
-                                                (
-                                                    ra + r as u128,
-                                                    ba + b as u128,
-                                                    ga + g as u128,
-                                                    aa + a as u128,
-                                                )
-                                            },
-                                        );
-                                    let size = data.data.len() as u128;
-                                    let color = (size != 0).then(|| {
-                                        Color32::from_rgba_premultiplied(
-                                            (r / size) as u8,
-                                            (b / size) as u8,
-                                            (g / size) as u8,
-                                            (a / size) as u8,
-                                        )
-                                    });
+                                    let (img, bounds) = match lookup.region {
+                                        Some(region) => region.crop(&img),
+                                        None => (img, WHOLE_IMAGE),
+                                    };
+                                    let mut data = ImageData::new(
+                                        &img,
+                                        *arc_clone.lock().expect("lock was poisoned"),
+                                    );
+                                    data.bounds = bounds;
+                                    let color = data.average_color();
                                     let send_result = px_tx_clone.send(Ok((lookup, data, color)));
                                     if let Err(err) = send_result {
                                         println!("failed to send {:?}", err);
@@ -166,8 +243,16 @@ impl ImageCache {
         files: &[&ImageFile],
     ) -> Option<&(ImageData, Option<Color32>)> {
         self.update();
+        if lookup.region.is_some() {
+            // only the latest zoomed in region of an image is worth keeping around
+            self.cache
+                .retain(|cached, _| cached.region.is_none() || *cached == lookup);
+            if !self.cache.contains_key(&lookup) && self.queued.insert(lookup) {
+                self.request(lookup, files);
+            }
+        }
         if self.cache.len() > 50 {
-            self.cache.retain(|ImageLookup { index }, _| {
+            self.cache.retain(|ImageLookup { index, .. }, _| {
                 let diff = if lookup.index.lt(index) {
                     index - lookup.index
                 } else {
@@ -179,6 +264,7 @@ impl ImageCache {
         for i in 0..=(num_cpus::get() / 2) {
             let guess_at_next = ImageLookup {
                 index: lookup.index.saturating_add(i),
+                region: None,
             };
             if !self.cache.contains_key(&guess_at_next) && self.queued.insert(guess_at_next) {
                 self.request(guess_at_next, files);
@@ -216,7 +302,7 @@ impl ImageCache {
         }
     }
 
-    fn request(&self, request: ImageLookup, files: &[&ImageFile]) {
+    fn request(&mut self, request: ImageLookup, files: &[&ImageFile]) {
         match files.get(request.index) {
             None => {
                 println!("invalid request occurred with lookup {:?}", request);
@@ -224,6 +310,8 @@ impl ImageCache {
             Some(file) => {
                 if let Err(err) = self.image_sender.try_send((request, file.as_path())) {
                     println!("failed to send due to {:?}", err);
+                    // let the next `get` try again rather than waiting forever
+                    self.queued.remove(&request);
                 }
             }
         }
@@ -1,18 +1,62 @@
 use std::convert::TryFrom;
 use std::ffi::OsStr;
 use std::fs::{DirEntry, File};
-use std::io::{BufRead, BufReader, BufWriter, ErrorKind, Write};
+use std::io::{BufRead, BufReader, ErrorKind};
 use std::path::{Path, PathBuf};
 
 use image::{DynamicImage, ImageError};
 
+use crate::app::atomic_file;
 use crate::app::bbox::BBox;
+use crate::app::orientation::{LabelGrid, Orientation};
+use crate::app::voc;
+use crate::app::voc::VocError;
 
-static SUPPORTED_IMAGE_TYPES: [&str; 3] = ["jpg", "JPG", "JPEG"];
+// compared against the lowercased extension
+static SUPPORTED_IMAGE_TYPES: [&str; 7] = ["jpg", "jpeg", "png", "bmp", "tif", "tiff", "webp"];
 
 #[derive(Debug, Ord, PartialOrd, Eq, PartialEq)]
 pub struct ImageFile(PathBuf);
 
+/// What kind of label file sits next to each image.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum LabelFormat {
+    /// a `.txt` file of normalized `<name> <x> <y> <width> <height>` lines
+    Darknet,
+    /// a pascal VOC `.xml` file with boxes in pixels
+    Voc,
+}
+
+/// How far an image has been labeled, as far as its label file can tell.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum LabelState {
+    /// there is no label file, nobody has saved anything for this image
+    Unvisited,
+    /// there is a label file without any boxes, someone checked and found nothing
+    VisitedEmpty,
+    Labeled,
+}
+
+impl LabelState {
+    /// the state `labels` will be in once they are saved
+    pub fn of(labels: &[BBox]) -> LabelState {
+        if labels.is_empty() {
+            LabelState::VisitedEmpty
+        } else {
+            LabelState::Labeled
+        }
+    }
+}
+
+/// everything needed to read and write the label file of an image
+#[derive(Copy, Clone)]
+pub struct LabelStorage<'a> {
+    pub format: LabelFormat,
+    pub grid: LabelGrid,
+    /// VOC files refer to names by name rather than index
+    pub names: &'a [String],
+}
+
 #[derive(Debug)]
 pub enum Error {
     NotAFile,
@@ -28,7 +72,96 @@ impl TryFrom<DirEntry> for ImageFile {
 }
 
 impl ImageFile {
-    pub fn load_labels(&self) -> Vec<BBox> {
+    /// the boxes in the label file, moved onto the oriented image if they were stored against
+    /// the raw one.
+    pub fn load_labels(&self, storage: LabelStorage<'_>) -> Vec<BBox> {
+        let labels = self.load_stored_labels(storage);
+        match storage.grid {
+            LabelGrid::Oriented => labels,
+            LabelGrid::Raw => {
+                let orientation = self.orientation();
+                labels
+                    .iter()
+                    .filter_map(|bbox| match orientation.orient(bbox) {
+                        Err(err) => {
+                            println!(
+                                "WARNING: error when orienting box {} of {} {}",
+                                bbox,
+                                self.0.display(),
+                                err
+                            );
+                            None
+                        }
+                        Ok(bbox) => Some(bbox),
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    pub fn label_path(&self, format: LabelFormat) -> PathBuf {
+        match format {
+            LabelFormat::Darknet => self.0.with_extension("txt"),
+            LabelFormat::Voc => self.0.with_extension("xml"),
+        }
+    }
+
+    pub fn has_label_file(&self, format: LabelFormat) -> bool {
+        self.label_path(format).is_file()
+    }
+
+    pub fn label_state(&self, storage: LabelStorage<'_>) -> LabelState {
+        if self.has_label_file(storage.format) {
+            LabelState::of(&self.load_stored_labels(storage))
+        } else {
+            LabelState::Unvisited
+        }
+    }
+
+    /// the boxes exactly as they are in the label file
+    pub(crate) fn load_stored_labels(&self, storage: LabelStorage<'_>) -> Vec<BBox> {
+        match storage.format {
+            LabelFormat::Darknet => self.load_darknet_labels(),
+            LabelFormat::Voc => self.load_voc_labels(storage),
+        }
+    }
+
+    fn load_voc_labels(&self, storage: LabelStorage<'_>) -> Vec<BBox> {
+        let xml_path = self.label_path(LabelFormat::Voc);
+        if !xml_path.exists() {
+            return Vec::new();
+        }
+        let size = match self.dimensions(storage.grid) {
+            Ok(size) => size,
+            Err(err) => {
+                println!("could not read the size of {}: {}", self.0.display(), err);
+                return Vec::new();
+            }
+        };
+        match voc::load(&xml_path, storage.names, size) {
+            Ok(labels) => labels
+                .into_iter()
+                .filter_map(|r_bbox| match r_bbox {
+                    Err(err) => {
+                        println!(
+                            "WARNING: error when parsing boxes from file {} {}",
+                            xml_path.display(),
+                            err
+                        );
+                        println!("ignoring for now . . . ");
+                        None
+                    }
+                    Ok(bbox) => Some(bbox),
+                })
+                .collect(),
+            Err(err) => {
+                println!("could not read {}: {}", xml_path.display(), err);
+                Vec::new()
+            }
+        }
+    }
+
+    fn load_darknet_labels(&self) -> Vec<BBox> {
         let parent = match self.0.parent() {
             None => panic!("oh god oh fuck where is the file"),
             Some(p) => p.to_str().unwrap(),
@@ -65,7 +198,50 @@ impl ImageFile {
         }
     }
 
-    pub fn save_labels(&self, labels: &[BBox]) -> std::io::Result<()> {
+    /// writes `labels` (which are always on the oriented image) to the label file, moving them
+    /// back onto the raw image first if that is what the label files are stored against.
+    pub fn save_labels(&self, labels: &[BBox], storage: LabelStorage<'_>) -> std::io::Result<()> {
+        let labels = match storage.grid {
+            LabelGrid::Oriented => labels.to_vec(),
+            LabelGrid::Raw => {
+                let orientation = self.orientation();
+                labels
+                    .iter()
+                    .map(|bbox| orientation.unorient(bbox))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|err| std::io::Error::new(ErrorKind::InvalidData, err))?
+            }
+        };
+        self.save_stored_labels(&labels, storage)
+    }
+
+    pub(crate) fn save_stored_labels(
+        &self,
+        labels: &[BBox],
+        storage: LabelStorage<'_>,
+    ) -> std::io::Result<()> {
+        match storage.format {
+            LabelFormat::Darknet => self.save_darknet_labels(labels),
+            LabelFormat::Voc => {
+                let size = self
+                    .dimensions(storage.grid)
+                    .map_err(|err| std::io::Error::new(ErrorKind::InvalidData, err))?;
+                voc::save(
+                    &self.label_path(LabelFormat::Voc),
+                    &self.file_name(),
+                    storage.names,
+                    size,
+                    labels,
+                )
+                .map_err(|err| match err {
+                    VocError::Io(err) => err,
+                    err => std::io::Error::new(ErrorKind::InvalidData, err),
+                })
+            }
+        }
+    }
+
+    fn save_darknet_labels(&self, labels: &[BBox]) -> std::io::Result<()> {
         let parent = self.0.parent().map_or(Some(""), Path::to_str);
         let parent = match parent {
             None => {
@@ -86,16 +262,10 @@ impl ImageFile {
             Some(stem) => stem,
         };
         let txt_path = format!("{}/{}.txt", parent, stem);
-        let f = File::options()
-            .create(true)
-            .write(true)
-            .open(&txt_path)?;
-        let result = f.set_len(0);
-        BufWriter::new(f).write_all(Self::labels_to_string(labels).into_bytes().as_slice())?;
-        // we evaluate the result after writing so we don't exit without writing SOMETHING to the
-        // file even if it has garbage left over at the end
-        result?;
-        Ok(())
+        atomic_file::write(
+            Path::new(&txt_path),
+            Self::labels_to_string(labels).as_bytes(),
+        )
     }
 
     fn labels_to_string(labels: &[BBox]) -> String {
@@ -106,8 +276,29 @@ impl ImageFile {
             .join("\n")
     }
 
+    /// the image as it should be displayed, with its EXIF orientation applied
     pub fn as_image(&self) -> Result<DynamicImage, ImageError> {
-        image::open(self.0.as_path())
+        Ok(self.orientation().apply(image::open(self.0.as_path())?))
+    }
+
+    /// the width and height of the grid labels are stored against, without decoding the image
+    pub fn dimensions(&self, grid: LabelGrid) -> Result<(u32, u32), ImageError> {
+        let (w, h) = image::image_dimensions(self.0.as_path())?;
+        match grid {
+            LabelGrid::Oriented if self.orientation().swaps_axes() => Ok((h, w)),
+            _ => Ok((w, h)),
+        }
+    }
+
+    pub fn file_name(&self) -> String {
+        self.0
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+
+    pub fn orientation(&self) -> Orientation {
+        Orientation::read(self.0.as_path())
     }
 
     pub fn as_path(&self) -> PathBuf {
@@ -118,13 +309,11 @@ impl ImageFile {
         let is_supported_image_type = entry
             .as_path()
             .extension()
-            .map(|ext| {
-                SUPPORTED_IMAGE_TYPES
-                    .iter()
-                    .map(OsStr::new)
-                    .any(|x| x == ext)
-            })
-            .unwrap_or_default();
+            .and_then(OsStr::to_str)
+            .is_some_and(|ext| {
+                let ext = ext.to_ascii_lowercase();
+                SUPPORTED_IMAGE_TYPES.iter().any(|x| *x == ext)
+            });
         if !entry.is_file() {
             Err(Error::NotAFile)
         } else if is_supported_image_type {
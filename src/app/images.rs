@@ -1,3 +1,4 @@
+use std::collections::BTreeSet;
 use std::iter::FromIterator;
 use std::ops::{Index, IndexMut};
 
@@ -9,7 +10,8 @@ pub struct Images(Vec<Image>);
 #[derive(Ord, PartialOrd, Eq, PartialEq)]
 pub struct Image {
     pub img: ImageFile,
-    pub marked: bool,
+    /// the names of the mark categories this image is in
+    pub marks: BTreeSet<String>,
 }
 
 impl IndexMut<usize> for Images {
@@ -30,13 +32,20 @@ impl Images {
     pub(crate) fn as_slice(&self) -> &[Image] {
         self.0.as_slice()
     }
+
+    pub(crate) fn as_mut_slice(&mut self) -> &mut [Image] {
+        self.0.as_mut_slice()
+    }
 }
 
 impl FromIterator<ImageFile> for Images {
     fn from_iter<T: IntoIterator<Item = ImageFile>>(iter: T) -> Self {
         let mut vec = iter
             .into_iter()
-            .map(|img| Image { img, marked: false })
+            .map(|img| Image {
+                img,
+                marks: BTreeSet::new(),
+            })
             .collect::<Vec<_>>();
         vec.sort();
         Images(vec)
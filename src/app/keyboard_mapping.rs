@@ -1,12 +1,21 @@
 use std::collections::BTreeMap;
 use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io::ErrorKind;
 use std::iter::FromIterator;
 use std::ops::{Deref, Index};
+use std::path::Path;
+use std::str::FromStr;
 
-use eframe::egui::{CtxRef, Key};
+use eframe::egui::{CtxRef, Event, Key, Modifiers};
 use eframe::epi::egui::PointerButton;
 
+use crate::app::atomic_file;
 use crate::app::keyboard_mapping::zero_to_nine::ZeroToNine;
+use crate::app::marks::MarkCategory;
 
 pub mod zero_to_nine {
     use std::convert::TryFrom;
@@ -50,14 +59,278 @@ pub enum Action {
     RemoveBox,
     NameNumber(ZeroToNine),
     Clear,
-    MarkAsSpecial,
+    /// toggles the mark category at this index in the settings
+    Mark(u8),
+    Undo,
+    Redo,
+    CycleSelection,
+    RelabelBox,
+    RelabelBoxNumber,
+    ResetView,
+    /// saves the current image even if it has no boxes, so it counts as reviewed
+    ConfirmReviewed,
+    NextUnvisited,
+    PrevUnvisited,
+    /// copies the highlighted box, or every box if none is highlighted
+    Copy,
+    CopyAll,
+    /// adds the copied boxes to the current image
+    Paste,
+    /// adds the boxes of the image before this one to the current image
+    CopyPrevious,
+    /// moves to the next image and suggests the boxes of this one where they moved to
+    TrackToNext,
+    /// keeps the suggested boxes and saves them
+    AcceptSuggestions,
+    DismissSuggestions,
 }
 
+impl Display for Action {
+    /// the name of the action in `keybindings.json`
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::NameNumber(ZeroToNine(n)) => write!(f, "NameNumber{}", n),
+            Action::Mark(i) => write!(f, "Mark{}", i),
+            action => write!(f, "{:?}", action),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum KeyBindingError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    UnknownAction(String),
+    InvalidTrigger(String),
+    Conflict(String),
+}
+
+impl Display for KeyBindingError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyBindingError::Io(err) => write!(f, "Io {}", err),
+            KeyBindingError::Json(err) => write!(f, "Json {}", err),
+            KeyBindingError::UnknownAction(explanation) => {
+                write!(f, "UnknownAction {}", explanation)
+            }
+            KeyBindingError::InvalidTrigger(explanation) => {
+                write!(f, "InvalidTrigger {}", explanation)
+            }
+            KeyBindingError::Conflict(explanation) => write!(f, "Conflict {}", explanation),
+        }
+    }
+}
+
+impl From<std::io::Error> for KeyBindingError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for KeyBindingError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Json(err)
+    }
+}
+
+impl Error for KeyBindingError {}
+
+/// every key that can be bound to an action
+pub(crate) static BINDABLE_KEYS: [Key; 51] = [
+    Key::A,
+    Key::B,
+    Key::C,
+    Key::D,
+    Key::E,
+    Key::F,
+    Key::G,
+    Key::H,
+    Key::I,
+    Key::J,
+    Key::K,
+    Key::L,
+    Key::M,
+    Key::N,
+    Key::O,
+    Key::P,
+    Key::Q,
+    Key::R,
+    Key::S,
+    Key::T,
+    Key::U,
+    Key::V,
+    Key::W,
+    Key::X,
+    Key::Y,
+    Key::Z,
+    Key::Num0,
+    Key::Num1,
+    Key::Num2,
+    Key::Num3,
+    Key::Num4,
+    Key::Num5,
+    Key::Num6,
+    Key::Num7,
+    Key::Num8,
+    Key::Num9,
+    Key::ArrowDown,
+    Key::ArrowLeft,
+    Key::ArrowRight,
+    Key::ArrowUp,
+    Key::Escape,
+    Key::Tab,
+    Key::Backspace,
+    Key::Enter,
+    Key::Space,
+    Key::Insert,
+    Key::Delete,
+    Key::Home,
+    Key::End,
+    Key::PageUp,
+    Key::PageDown,
+];
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum EventTrigger {
+    /// a key pressed without holding ctrl, shift or alt, so `C` does not also fire on `Ctrl+C`
     Key(Key),
+    /// a key pressed while holding exactly these modifiers
+    Chord(Modifiers, Key),
     PointerButton(PointerButton),
 }
 
+impl EventTrigger {
+    /// `key` while holding ctrl (cmd on mac)
+    fn command(key: Key) -> EventTrigger {
+        EventTrigger::Chord(
+            Modifiers {
+                command: true,
+                ..Modifiers::default()
+            },
+            key,
+        )
+    }
+
+    /// `key` while holding ctrl (cmd on mac) and shift
+    fn command_shift(key: Key) -> EventTrigger {
+        EventTrigger::Chord(
+            Modifiers {
+                command: true,
+                shift: true,
+                ..Modifiers::default()
+            },
+            key,
+        )
+    }
+}
+
+/// the names of the pointer buttons in `keybindings.json`
+const POINTER_BUTTONS: [(&str, PointerButton); 3] = [
+    ("MousePrimary", PointerButton::Primary),
+    ("MouseSecondary", PointerButton::Secondary),
+    ("MouseMiddle", PointerButton::Middle),
+];
+
+impl Display for EventTrigger {
+    /// the trigger as it is written in `keybindings.json`, such as `D`, `Ctrl+Shift+Z` or
+    /// `MouseMiddle`
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            EventTrigger::Key(key) => write!(f, "{:?}", key),
+            EventTrigger::Chord(modifiers, key) => {
+                for (held, name) in &[
+                    (modifiers.command, "Ctrl"),
+                    (modifiers.shift, "Shift"),
+                    (modifiers.alt, "Alt"),
+                ] {
+                    if *held {
+                        write!(f, "{}+", name)?;
+                    }
+                }
+                write!(f, "{:?}", key)
+            }
+            EventTrigger::PointerButton(button) => {
+                let name = POINTER_BUTTONS
+                    .iter()
+                    .find(|(_, it)| it == button)
+                    .map_or("?", |(name, _)| name);
+                write!(f, "{}", name)
+            }
+        }
+    }
+}
+
+impl FromStr for EventTrigger {
+    type Err = KeyBindingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((_, button)) = POINTER_BUTTONS.iter().find(|(name, _)| *name == s) {
+            return Ok(EventTrigger::PointerButton(*button));
+        }
+        let mut parts = s.split('+').map(str::trim).collect::<Vec<_>>();
+        let key_name = parts.pop().unwrap_or_default();
+        let key = BINDABLE_KEYS
+            .iter()
+            .find(|key| format!("{:?}", key) == key_name)
+            .ok_or_else(|| {
+                KeyBindingError::InvalidTrigger(format!("{} is not a key or mouse button", s))
+            })?;
+        let mut modifiers = Modifiers::default();
+        for modifier in parts {
+            match modifier {
+                // `command` so the same binding is cmd on mac
+                "Ctrl" | "Cmd" => modifiers.command = true,
+                "Shift" => modifiers.shift = true,
+                "Alt" => modifiers.alt = true,
+                other => {
+                    return Err(KeyBindingError::InvalidTrigger(format!(
+                        "{} in {} is not one of [Ctrl, Shift, Alt]",
+                        other, s
+                    )))
+                }
+            }
+        }
+        if modifiers == Modifiers::default() {
+            Ok(EventTrigger::Key(*key))
+        } else {
+            Ok(EventTrigger::Chord(modifiers, *key))
+        }
+    }
+}
+
+impl EventTrigger {
+    /// The key or mouse button pressed this frame, for rebinding an action to it. The primary
+    /// button is left out since it is also what clicks the rebind button, and `Escape` is left
+    /// out so it can cancel.
+    pub(crate) fn pressed(ctx: &CtxRef) -> Option<EventTrigger> {
+        ctx.input().events.iter().find_map(|event| match event {
+            Event::Key {
+                key,
+                pressed: true,
+                modifiers: held,
+            } if *key != Key::Escape && BINDABLE_KEYS.contains(key) => {
+                let modifiers = Modifiers {
+                    command: held.command,
+                    shift: held.shift,
+                    alt: held.alt,
+                    ..Modifiers::default()
+                };
+                Some(if modifiers == Modifiers::default() {
+                    EventTrigger::Key(*key)
+                } else {
+                    EventTrigger::Chord(modifiers, *key)
+                })
+            }
+            Event::PointerButton {
+                button,
+                pressed: true,
+                ..
+            } if *button != PointerButton::Primary => Some(EventTrigger::PointerButton(*button)),
+            _ => None,
+        })
+    }
+}
+
 impl From<Key> for EventTrigger {
     fn from(key: Key) -> Self {
         EventTrigger::Key(key)
@@ -74,10 +347,167 @@ pub struct KeyboardMapping(BTreeMap<Action, EventTrigger>);
 
 impl KeyboardMapping {
     pub(crate) fn is_triggered(&self, p0: Action, ctx: &CtxRef) -> bool {
-        match self.0[&p0] {
-            EventTrigger::Key(k) => ctx.input().key_pressed(k),
-            EventTrigger::PointerButton(pb) => ctx.input().pointer.button_down(pb),
+        match self.0.get(&p0) {
+            None => false,
+            Some(&EventTrigger::Key(k)) => {
+                holding_exactly(ctx, Modifiers::default()) && ctx.input().key_pressed(k)
+            }
+            Some(&EventTrigger::Chord(m, k)) => {
+                holding_exactly(ctx, m) && ctx.input().key_pressed(k)
+            }
+            Some(&EventTrigger::PointerButton(pb)) => ctx.input().events.iter().any(|event| {
+                matches!(event, Event::PointerButton { button, pressed: true, .. } if *button == pb)
+            }),
+        }
+    }
+
+    /// binds a `Mark` action to the key of each category, replacing any from before
+    pub(crate) fn bind_marks(&mut self, categories: &[MarkCategory]) {
+        self.0
+            .retain(|action, _| !matches!(action, Action::Mark(_)));
+        for (i, category) in (0..=u8::MAX).zip(categories) {
+            self.0.insert(Action::Mark(i), category.key.into());
+        }
+    }
+
+    /// the first key that no action is bound to, for a new mark category
+    pub(crate) fn free_key(&self) -> Option<Key> {
+        BINDABLE_KEYS.iter().copied().find(|key| {
+            *key != Key::Escape
+                && !self
+                    .0
+                    .values()
+                    .any(|trigger| matches!(trigger, EventTrigger::Key(k) if k == key))
+        })
+    }
+
+    /// the first action other than `action` that `key` triggers
+    pub(crate) fn bound_to(&self, key: Key, action: Action) -> Option<Action> {
+        self.0
+            .iter()
+            .find(|(other, trigger)| {
+                **other != action && matches!(trigger, EventTrigger::Key(k) if *k == key)
+            })
+            .map(|(other, _)| *other)
+    }
+}
+
+/// whether ctrl, shift and alt are held exactly as in `modifiers`, comparing `command` rather
+/// than `ctrl` so the same chord works on mac
+fn holding_exactly(ctx: &CtxRef, modifiers: Modifiers) -> bool {
+    let held = ctx.input().modifiers;
+    held.command == modifiers.command && held.shift == modifiers.shift && held.alt == modifiers.alt
+}
+
+impl KeyboardMapping {
+    /// where the key bindings are read from, next to `settings.json`
+    pub(crate) const FILE_NAME: &'static str = "keybindings.json";
+
+    /// Reads `keybindings.json`, an object from action names to triggers such as
+    /// `{"NextImage": "ArrowRight", "Redo": "Ctrl+Y"}`. Actions it leaves out keep their default
+    /// binding, with a warning. If there is no file every action has its default binding.
+    ///
+    /// # Errors
+    /// if the file cannot be read, names an action or trigger that does not exist, or binds two
+    /// actions to the same trigger
+    pub fn from_file() -> Result<KeyboardMapping, KeyBindingError> {
+        let contents = match fs::read_to_string(Self::FILE_NAME) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(KeyboardMapping::default()),
+            Err(err) => return Err(err.into()),
+        };
+        let written: BTreeMap<String, String> = serde_json::from_str(&contents)?;
+        let defaults = Self::default_mappings();
+        let mut bindings = BTreeMap::new();
+        for (name, trigger) in &written {
+            let action = defaults
+                .iter()
+                .map(|(action, _)| *action)
+                .find(|action| action.to_string() == *name)
+                .ok_or_else(|| {
+                    KeyBindingError::UnknownAction(format!(
+                        "{} is not an action, mark keys are set with their category in settings",
+                        name
+                    ))
+                })?;
+            bindings.insert(action, trigger.parse()?);
+        }
+        let missing = defaults
+            .into_iter()
+            .filter(|(action, _)| !bindings.contains_key(action))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            println!(
+                "WARNING: {} does not bind {}, using the defaults for them",
+                Self::FILE_NAME,
+                missing
+                    .iter()
+                    .map(|(action, trigger)| format!("{} ({})", action, trigger))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        bindings.extend(missing);
+        let mapping = KeyboardMapping(bindings);
+        match mapping.conflicts().first() {
+            Some((trigger, a, b)) => Err(KeyBindingError::Conflict(format!(
+                "{} is bound to both {} and {}",
+                trigger, a, b
+            ))),
+            None => Ok(mapping),
+        }
+    }
+
+    /// Binds `action` to `trigger` unless another action already has it.
+    ///
+    /// # Errors
+    /// the action `trigger` is already bound to
+    pub(crate) fn rebind(&mut self, action: Action, trigger: EventTrigger) -> Result<(), Action> {
+        if let Some((other, _)) = self
+            .0
+            .iter()
+            .find(|(other, other_trigger)| **other != action && **other_trigger == trigger)
+        {
+            return Err(*other);
+        }
+        self.0.insert(action, trigger);
+        Ok(())
+    }
+
+    /// puts every action back on its default binding, the mark keys stay as they are in settings
+    pub(crate) fn reset(&mut self) {
+        self.0.retain(|action, _| matches!(action, Action::Mark(_)));
+        self.0.extend(Self::default_mappings());
+    }
+
+    /// Writes every binding except the mark keys to `keybindings.json`, in the form `from_file`
+    /// reads.
+    ///
+    /// # Errors
+    /// if the file cannot be written
+    pub(crate) fn save(&self) -> Result<(), KeyBindingError> {
+        let written = self
+            .0
+            .iter()
+            .filter(|(action, _)| !matches!(action, Action::Mark(_)))
+            .map(|(action, trigger)| (action.to_string(), trigger.to_string()))
+            .collect::<BTreeMap<_, _>>();
+        let json = serde_json::to_string_pretty(&written)?;
+        atomic_file::write(Path::new(Self::FILE_NAME), json.as_bytes())?;
+        Ok(())
+    }
+
+    /// every pair of actions bound to the same trigger
+    pub(crate) fn conflicts(&self) -> Vec<(EventTrigger, Action, Action)> {
+        let mut conflicts = Vec::new();
+        for (i, (action, trigger)) in self.0.iter().enumerate() {
+            for (other, other_trigger) in self.0.iter().skip(i + 1) {
+                if trigger == other_trigger {
+                    conflicts.push((*trigger, *action, *other));
+                }
+            }
         }
+        conflicts
     }
 }
 
@@ -92,7 +522,6 @@ impl Deref for KeyboardMapping {
 impl KeyboardMapping {
     fn default_mappings() -> Vec<(Action, EventTrigger)> {
         vec![
-            (Action::MarkAsSpecial, Key::M.into()),
             (Action::NextImage, Key::D.into()),
             (Action::PrevImage, Key::A.into()),
             (Action::NextName, Key::S.into()),
@@ -109,6 +538,22 @@ impl KeyboardMapping {
             (Action::NameNumber(8.try_into().unwrap()), Key::Num8.into()),
             (Action::NameNumber(9.try_into().unwrap()), Key::Num9.into()),
             (Action::Clear, Key::C.into()),
+            (Action::Undo, EventTrigger::command(Key::Z)),
+            (Action::Redo, EventTrigger::command_shift(Key::Z)),
+            (Action::CycleSelection, Key::Tab.into()),
+            (Action::RelabelBox, Key::E.into()),
+            (Action::RelabelBoxNumber, Key::Q.into()),
+            (Action::ResetView, Key::F.into()),
+            (Action::ConfirmReviewed, Key::Space.into()),
+            (Action::NextUnvisited, Key::N.into()),
+            (Action::PrevUnvisited, Key::P.into()),
+            (Action::Copy, EventTrigger::command(Key::C)),
+            (Action::CopyAll, EventTrigger::command_shift(Key::C)),
+            (Action::Paste, EventTrigger::command(Key::V)),
+            (Action::CopyPrevious, Key::V.into()),
+            (Action::TrackToNext, Key::T.into()),
+            (Action::AcceptSuggestions, Key::Enter.into()),
+            (Action::DismissSuggestions, Key::Backspace.into()),
         ]
     }
 }
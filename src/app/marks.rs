@@ -0,0 +1,190 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use eframe::egui::Key;
+
+use crate::app::atomic_file;
+use crate::app::images::Images;
+
+/// A kind of mark, such as "delete" or "blurry", and the key that toggles it.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct MarkCategory {
+    pub name: String,
+    pub key: Key,
+}
+
+impl MarkCategory {
+    pub(crate) fn defaults() -> Vec<MarkCategory> {
+        vec![MarkCategory {
+            name: String::from("marked"),
+            key: Key::M,
+        }]
+    }
+}
+
+/// The marked images of every session, kept in `marks.json` next to the names file as a list of
+/// image paths for each category.
+pub struct Marks {
+    file: PathBuf,
+    /// paths of images that are not in the images directory, kept so saving does not drop them
+    others: BTreeMap<String, Vec<String>>,
+}
+
+impl Marks {
+    const FILE_NAME: &'static str = "marks.json";
+
+    /// reads `marks.json` in `names_dir` and marks the images in it. If there is none yet, the
+    /// `marked.txt` and `marked_<timestamp>.txt` files older versions made are combined into
+    /// `default_category`.
+    pub(crate) fn load(names_dir: &Path, images: &mut Images, default_category: &str) -> Marks {
+        let file = names_dir.join(Self::FILE_NAME);
+        let categories = Self::read(names_dir, default_category);
+        // matched by file name so marks survive the images directory moving
+        let by_name = images
+            .as_slice()
+            .iter()
+            .enumerate()
+            .map(|(i, image)| (image.img.file_name(), i))
+            .collect::<BTreeMap<_, _>>();
+        let mut others: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (category, paths) in &categories {
+            for path in paths.iter().filter(|path| !path.is_empty()) {
+                let index = Path::new(path)
+                    .file_name()
+                    .and_then(|name| by_name.get(name.to_string_lossy().as_ref()));
+                match index {
+                    Some(&i) => {
+                        images[i].marks.insert(category.clone());
+                    }
+                    None => others
+                        .entry(category.clone())
+                        .or_default()
+                        .push(path.clone()),
+                }
+            }
+        }
+        let marks = Marks { file, others };
+        if !marks.file.exists() && !categories.is_empty() {
+            if let Err(err) = marks.save(images) {
+                println!("failed to combine old marked files: {}", err);
+            }
+        }
+        marks
+    }
+
+    /// the image paths in each category, without touching any files
+    fn read(names_dir: &Path, default_category: &str) -> BTreeMap<String, Vec<String>> {
+        let file = names_dir.join(Self::FILE_NAME);
+        if let Ok(contents) = fs::read_to_string(&file) {
+            serde_json::from_str(&contents).unwrap_or_else(|err| {
+                panic!(
+                    "{} is invalid ({}), fix or delete it if you are fine losing your marks",
+                    file.display(),
+                    err
+                )
+            })
+        } else {
+            let lines = Self::read_legacy_files(names_dir);
+            if lines.is_empty() {
+                BTreeMap::new()
+            } else {
+                vec![(default_category.to_string(), lines)]
+                    .into_iter()
+                    .collect()
+            }
+        }
+    }
+
+    /// the file names of the images in any category
+    pub(crate) fn marked_file_names(names_dir: &Path) -> BTreeSet<String> {
+        Self::read(names_dir, "marked")
+            .values()
+            .flatten()
+            .filter_map(|path| Path::new(path).file_name())
+            .map(|name| name.to_string_lossy().to_string())
+            .collect()
+    }
+
+    fn read_legacy_files(names_dir: &Path) -> Vec<String> {
+        let mut lines = BTreeSet::new();
+        for entry in fs::read_dir(names_dir).into_iter().flatten().flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let is_marked_file = name == "marked.txt" || name.starts_with("marked_");
+            if is_marked_file && entry.path().extension() == Some(OsStr::new("txt")) {
+                match fs::read_to_string(entry.path()) {
+                    Ok(contents) => lines.extend(contents.lines().map(String::from)),
+                    Err(err) => println!("could not read {}: {}", name, err),
+                }
+            }
+        }
+        lines.into_iter().collect()
+    }
+
+    /// moves every image in the category `from` to `to` and saves, nothing changes if saving fails
+    pub(crate) fn rename(
+        &mut self,
+        images: &mut Images,
+        from: &str,
+        to: &str,
+    ) -> std::io::Result<()> {
+        let rename = |marks: &mut Marks, images: &mut Images, from: &str, to: &str| {
+            for image in images.as_mut_slice() {
+                if image.marks.remove(from) {
+                    image.marks.insert(to.to_string());
+                }
+            }
+            if let Some(paths) = marks.others.remove(from) {
+                marks
+                    .others
+                    .entry(to.to_string())
+                    .or_default()
+                    .extend(paths);
+            }
+        };
+        rename(self, images, from, to);
+        if let Err(err) = self.save(images) {
+            rename(self, images, to, from);
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// takes every image out of `category` and saves, nothing changes if saving fails
+    pub(crate) fn remove(&mut self, images: &mut Images, category: &str) -> std::io::Result<()> {
+        let marked = images
+            .as_mut_slice()
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(i, image)| image.marks.remove(category).then_some(i))
+            .collect::<Vec<_>>();
+        let others = self.others.remove(category);
+        if let Err(err) = self.save(images) {
+            for i in marked {
+                images[i].marks.insert(category.to_string());
+            }
+            if let Some(others) = others {
+                self.others.insert(category.to_string(), others);
+            }
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// rewrites the marks file with every marked image
+    pub(crate) fn save(&self, images: &Images) -> std::io::Result<()> {
+        let mut categories = self.others.clone();
+        for image in images.as_slice() {
+            for category in &image.marks {
+                categories
+                    .entry(category.clone())
+                    .or_default()
+                    .push(image.img.as_path().to_string_lossy().to_string());
+            }
+        }
+        let json = serde_json::to_string_pretty(&categories)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+        atomic_file::write(&self.file, json.as_bytes())
+    }
+}
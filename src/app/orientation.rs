@@ -0,0 +1,121 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use eframe::egui::Pos2;
+use exif::{In, Reader, Tag};
+use image::DynamicImage;
+
+use crate::app::bbox::{BBox, BBoxError};
+
+/// Which pixel grid the coordinates in label files refer to.
+#[derive(serde::Serialize, serde::Deserialize, Copy, Clone, Debug, Eq, PartialEq)]
+pub enum LabelGrid {
+    /// the image as it is stored on disk, ignoring EXIF orientation
+    Raw,
+    /// the image after EXIF orientation is applied, which is what we display
+    Oriented,
+}
+
+/// The EXIF orientation tag, how the stored pixels have to be transformed to be upright.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Orientation {
+    Normal,
+    FlipHorizontal,
+    Rotate180,
+    FlipVertical,
+    Transpose,
+    Rotate90,
+    Transverse,
+    Rotate270,
+}
+
+impl Orientation {
+    /// reads the orientation from the EXIF data of `path`, anything without a valid tag is upright
+    pub(crate) fn read(path: &Path) -> Orientation {
+        let value = File::open(path).ok().and_then(|f| {
+            Reader::new()
+                .read_from_container(&mut BufReader::new(f))
+                .ok()?
+                .get_field(Tag::Orientation, In::PRIMARY)?
+                .value
+                .get_uint(0)
+        });
+        match value {
+            Some(2) => Orientation::FlipHorizontal,
+            Some(3) => Orientation::Rotate180,
+            Some(4) => Orientation::FlipVertical,
+            Some(5) => Orientation::Transpose,
+            Some(6) => Orientation::Rotate90,
+            Some(7) => Orientation::Transverse,
+            Some(8) => Orientation::Rotate270,
+            _ => Orientation::Normal,
+        }
+    }
+
+    pub(crate) fn apply(self, img: DynamicImage) -> DynamicImage {
+        match self {
+            Orientation::Normal => img,
+            Orientation::FlipHorizontal => img.fliph(),
+            Orientation::Rotate180 => img.rotate180(),
+            Orientation::FlipVertical => img.flipv(),
+            Orientation::Transpose => img.rotate90().fliph(),
+            Orientation::Rotate90 => img.rotate90(),
+            Orientation::Transverse => img.rotate270().fliph(),
+            Orientation::Rotate270 => img.rotate270(),
+        }
+    }
+
+    fn inverse(self) -> Orientation {
+        match self {
+            Orientation::Rotate90 => Orientation::Rotate270,
+            Orientation::Rotate270 => Orientation::Rotate90,
+            // everything else undoes itself
+            other => other,
+        }
+    }
+
+    pub(crate) fn swaps_axes(self) -> bool {
+        matches!(
+            self,
+            Orientation::Transpose
+                | Orientation::Rotate90
+                | Orientation::Transverse
+                | Orientation::Rotate270
+        )
+    }
+
+    /// where a normalized point on the raw image ends up on the oriented one
+    fn map(self, Pos2 { x, y }: Pos2) -> Pos2 {
+        let (x, y) = match self {
+            Orientation::Normal => (x, y),
+            Orientation::FlipHorizontal => (1.0 - x, y),
+            Orientation::Rotate180 => (1.0 - x, 1.0 - y),
+            Orientation::FlipVertical => (x, 1.0 - y),
+            Orientation::Transpose => (y, x),
+            Orientation::Rotate90 => (1.0 - y, x),
+            Orientation::Transverse => (1.0 - y, 1.0 - x),
+            Orientation::Rotate270 => (y, 1.0 - x),
+        };
+        Pos2 { x, y }
+    }
+
+    /// moves a box on the raw image to the same pixels on the oriented image
+    pub(crate) fn orient(self, bbox: &BBox) -> Result<BBox, BBoxError> {
+        let Pos2 { x, y } = self.map(Pos2 {
+            x: bbox.x,
+            y: bbox.y,
+        });
+        if self.swaps_axes() {
+            BBox::new(bbox.name, bbox.height, bbox.width, x, y)
+        } else {
+            BBox::new(bbox.name, bbox.width, bbox.height, x, y)
+        }
+        .map(|oriented| oriented.with_flags_of(bbox))
+    }
+
+    /// moves a box on the oriented image back to the same pixels on the raw image
+    pub(crate) fn unorient(self, bbox: &BBox) -> Result<BBox, BBoxError> {
+        self.inverse().orient(bbox)
+    }
+}
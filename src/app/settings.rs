@@ -2,6 +2,9 @@ use std::fs::File;
 use std::io::BufReader;
 use std::num::NonZeroU32;
 
+use crate::app::marks::MarkCategory;
+use crate::app::orientation::LabelGrid;
+
 trait Partial<T>
 where
     T: Default,
@@ -9,6 +12,7 @@ where
     fn fill(&self) -> T;
 }
 
+#[allow(clippy::struct_excessive_bools)]
 #[derive(serde::Serialize, Debug)]
 pub struct Settings {
     pub key_combo_trigger_ms: u128,
@@ -20,6 +24,11 @@ pub struct Settings {
     pub display_cursor_name: bool,
     pub save_interval_seconds: NonZeroU32,
     pub dynamic_crosshair: bool,
+    pub scroll_to_zoom: bool,
+    pub label_grid: LabelGrid,
+    pub mark_categories: Vec<MarkCategory>,
+    /// moving to the next image tracks the boxes of this one into it
+    pub track_on_next: bool,
 }
 
 #[derive(serde::Deserialize)]
@@ -33,6 +42,10 @@ struct PartialSettings {
     display_cursor_name: Option<bool>,
     save_interval_seconds: Option<NonZeroU32>,
     dynamic_crosshair: Option<bool>,
+    scroll_to_zoom: Option<bool>,
+    label_grid: Option<LabelGrid>,
+    mark_categories: Option<Vec<MarkCategory>>,
+    track_on_next: Option<bool>,
 }
 
 impl Partial<Settings> for PartialSettings {
@@ -58,6 +71,13 @@ impl Partial<Settings> for PartialSettings {
                 .save_interval_seconds
                 .unwrap_or(default.save_interval_seconds),
             dynamic_crosshair: self.dynamic_crosshair.unwrap_or(default.dynamic_crosshair),
+            scroll_to_zoom: self.scroll_to_zoom.unwrap_or(default.scroll_to_zoom),
+            label_grid: self.label_grid.unwrap_or(default.label_grid),
+            mark_categories: self
+                .mark_categories
+                .clone()
+                .unwrap_or(default.mark_categories),
+            track_on_next: self.track_on_next.unwrap_or(default.track_on_next),
         }
     }
 }
@@ -74,6 +94,10 @@ impl Default for Settings {
             display_cursor_name: true,
             save_interval_seconds: NonZeroU32::new(20).unwrap(),
             dynamic_crosshair: false,
+            scroll_to_zoom: false,
+            label_grid: LabelGrid::Oriented,
+            mark_categories: MarkCategory::defaults(),
+            track_on_next: false,
         }
     }
 }
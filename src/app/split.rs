@@ -0,0 +1,241 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rand::seq::SliceRandom;
+use rand_chacha::rand_core::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::app::image_file::LabelStorage;
+use crate::app::images::Images;
+use crate::app::marks::Marks;
+
+/// How `split` divides the images, the rest of them go to training.
+pub struct SplitOptions {
+    pub valid: f32,
+    pub test: f32,
+    /// the same seed and images always give the same split
+    pub seed: u64,
+    /// split each class on its own so every part gets its share of rare classes
+    pub stratify: bool,
+}
+
+impl Default for SplitOptions {
+    fn default() -> Self {
+        SplitOptions {
+            valid: 0.2,
+            test: 0.0,
+            seed: 0,
+            stratify: false,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Parts {
+    train: Vec<String>,
+    valid: Vec<String>,
+    test: Vec<String>,
+}
+
+/// Shuffles the reviewed images that are not marked into `train.txt`, `valid.txt` and `test.txt`
+/// in `out_dir`, and writes a darknet `obj.data` and an ultralytics `data.yaml` that point at
+/// them.
+///
+/// # Errors
+/// if a file cannot be written
+pub fn split(
+    images: &Images,
+    storage: LabelStorage<'_>,
+    names_file: &Path,
+    out_dir: &Path,
+    options: &SplitOptions,
+) -> std::io::Result<String> {
+    let marked = Marks::marked_file_names(out_dir);
+    let mut groups: BTreeMap<Option<usize>, Vec<String>> = BTreeMap::new();
+    let reviewed = images
+        .as_slice()
+        .iter()
+        .filter(|image| {
+            image.img.has_label_file(storage.format) && !marked.contains(&image.img.file_name())
+        })
+        .map(|image| (&image.img, image.img.load_stored_labels(storage)))
+        .collect::<Vec<_>>();
+    let mut box_counts = BTreeMap::new();
+    for (_, labels) in &reviewed {
+        for bbox in labels {
+            *box_counts.entry(bbox.name).or_insert(0_usize) += 1;
+        }
+    }
+    for (image, labels) in &reviewed {
+        // images go with their rarest class so the classes with few boxes are spread evenly
+        let group = if options.stratify {
+            labels
+                .iter()
+                .map(|bbox| bbox.name)
+                .min_by_key(|name| (box_counts[name], *name))
+        } else {
+            None
+        };
+        groups
+            .entry(group)
+            .or_default()
+            .push(absolute(&image.as_path()));
+    }
+    let mut rng = ChaCha8Rng::seed_from_u64(options.seed);
+    let mut parts = Parts::default();
+    for paths in groups.values_mut() {
+        paths.shuffle(&mut rng);
+        let valid = share(paths.len(), options.valid);
+        let test = share(paths.len(), options.test).min(paths.len() - valid);
+        parts.valid.extend(paths.drain(..valid));
+        parts.test.extend(paths.drain(..test));
+        parts.train.append(paths);
+    }
+    let out_dir = PathBuf::from(absolute(out_dir));
+    let mut lists = vec![("train", &parts.train), ("valid", &parts.valid)];
+    if !parts.test.is_empty() {
+        lists.push(("test", &parts.test));
+    }
+    for (name, paths) in &lists {
+        let lines = paths.iter().map(|path| format!("{}\n", path));
+        fs::write(
+            out_dir.join(format!("{}.txt", name)),
+            lines.collect::<String>(),
+        )?;
+    }
+    let list_path = |name: &str| absolute(&out_dir.join(format!("{}.txt", name)));
+    let mut obj_data = vec![
+        format!("classes = {}", storage.names.len()),
+        format!("train = {}", list_path("train")),
+        format!("valid = {}", list_path("valid")),
+    ];
+    let mut data_yaml = vec![
+        format!("path: {}", out_dir.display()),
+        String::from("train: train.txt"),
+        String::from("val: valid.txt"),
+    ];
+    if !parts.test.is_empty() {
+        obj_data.push(format!("test = {}", list_path("test")));
+        data_yaml.push(String::from("test: test.txt"));
+    }
+    obj_data.push(format!("names = {}", absolute(names_file)));
+    obj_data.push(String::from("backup = backup/"));
+    data_yaml.push(format!("nc: {}", storage.names.len()));
+    // a json list of strings is also a yaml one, and quotes names that yaml would misread
+    data_yaml.push(format!(
+        "names: {}",
+        serde_json::to_string(storage.names).map_err(std::io::Error::from)?
+    ));
+    fs::write(out_dir.join("obj.data"), obj_data.join("\n") + "\n")?;
+    fs::write(out_dir.join("data.yaml"), data_yaml.join("\n") + "\n")?;
+    let left_out = images
+        .as_slice()
+        .iter()
+        .filter(|image| marked.contains(&image.img.file_name()))
+        .count();
+    Ok(format!(
+        "wrote {} training, {} validation and {} test images to {}, left out {} marked images",
+        parts.train.len(),
+        parts.valid.len(),
+        parts.test.len(),
+        out_dir.display(),
+        left_out
+    ))
+}
+
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+fn share(len: usize, ratio: f32) -> usize {
+    ((len as f32 * ratio).round() as usize).min(len)
+}
+
+fn absolute(path: &Path) -> String {
+    fs::canonicalize(path)
+        .unwrap_or_else(|_| path.to_path_buf())
+        .to_string_lossy()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::image_file::{ImageFile, LabelFormat};
+    use crate::app::orientation::LabelGrid;
+
+    /// twenty images, all but the last two reviewed, with a rare class on every fifth
+    fn dataset(dir: &Path) -> Images {
+        fs::create_dir_all(dir).unwrap();
+        for i in 0..20 {
+            let image = dir.join(format!("{:02}.png", i));
+            fs::write(&image, b"").unwrap();
+            if i < 18 {
+                let name = if i % 5 == 0 { 1 } else { 0 };
+                fs::write(
+                    image.with_extension("txt"),
+                    format!("{} 0.5 0.5 0.2 0.2\n", name),
+                )
+                .unwrap();
+            }
+        }
+        fs::read_dir(dir)
+            .unwrap()
+            .filter_map(|entry| ImageFile::new(entry.unwrap().path()).ok())
+            .collect()
+    }
+
+    fn lists(dir: &Path, images: &Images, options: &SplitOptions) -> (String, String) {
+        let names = [String::from("common"), String::from("rare")];
+        let storage = LabelStorage {
+            format: LabelFormat::Darknet,
+            grid: LabelGrid::Oriented,
+            names: &names,
+        };
+        split(images, storage, &dir.join("obj.names"), dir, options).unwrap();
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        (read("train.txt"), read("valid.txt"))
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_split() {
+        let dir = std::env::temp_dir().join(format!("yolo_mark_rs_split_{}", std::process::id()));
+        let images = dataset(&dir);
+        let options = SplitOptions {
+            valid: 0.25,
+            seed: 7,
+            ..SplitOptions::default()
+        };
+        let (train, valid) = lists(&dir, &images, &options);
+        assert_eq!(
+            lists(&dir, &images, &options),
+            (train.clone(), valid.clone())
+        );
+        // every reviewed image goes in exactly one list, unvisited ones in none
+        let mut all = train.lines().chain(valid.lines()).collect::<Vec<_>>();
+        assert_eq!(valid.lines().count(), 5);
+        all.sort_unstable();
+        all.dedup();
+        assert_eq!(all.len(), 18);
+        assert!(all
+            .iter()
+            .all(|path| !path.ends_with("18.png") && !path.ends_with("19.png")));
+
+        let stratified = SplitOptions {
+            stratify: true,
+            ..options
+        };
+        let (_, valid) = lists(&dir, &images, &stratified);
+        let rare = ["00.png", "05.png", "10.png", "15.png"];
+        assert_eq!(
+            valid
+                .lines()
+                .filter(|path| rare.iter().any(|rare| path.ends_with(rare)))
+                .count(),
+            1
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
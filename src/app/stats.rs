@@ -0,0 +1,356 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::thread;
+
+use crossbeam::channel::{Receiver, Sender};
+use eframe::egui;
+use eframe::egui::{ProgressBar, Ui};
+
+use crate::app::image_file::{ImageFile, LabelFormat, LabelState, LabelStorage};
+use crate::app::images::Images;
+use crate::app::orientation::LabelGrid;
+
+/// upper bounds of the box size buckets, as the square root of the share of the image covered
+const SIZE_BUCKETS: [f32; 10] = [0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9, f32::INFINITY];
+/// upper bounds of the width / height buckets
+const ASPECT_BUCKETS: [f32; 7] = [0.25, 0.5, 0.8, 1.25, 2.0, 4.0, f32::INFINITY];
+
+/// label files to read, with everything needed to read them off the ui thread
+struct ScanRequest {
+    images: Vec<(usize, PathBuf)>,
+    format: LabelFormat,
+    grid: LabelGrid,
+    names: Vec<String>,
+}
+
+/// what the scan found in the label file of one image
+struct ImageSummary {
+    /// `None` if the image could not be opened
+    state: Option<LabelState>,
+    boxes: Vec<BoxSummary>,
+}
+
+struct BoxSummary {
+    name: usize,
+    /// square root of the share of the image the box covers
+    size: f32,
+    /// width over height in pixels, if the image size could be read
+    aspect: Option<f32>,
+}
+
+/// Counts over every label file in the dataset, read on a background thread and refreshed one
+/// image at a time as labels are saved.
+pub struct DatasetStats {
+    images: Vec<Option<ImageSummary>>,
+    request_sender: Sender<ScanRequest>,
+    summary_receiver: Receiver<(usize, ImageSummary)>,
+    totals: Totals,
+    // the totals are out of date with `images`
+    dirty: bool,
+}
+
+#[derive(Default)]
+struct Totals {
+    scanned: usize,
+    unvisited: usize,
+    visited_empty: usize,
+    labeled: usize,
+    unreadable: usize,
+    /// boxes and images with at least one box, by name index
+    classes: BTreeMap<usize, (usize, usize)>,
+    sizes: [usize; SIZE_BUCKETS.len()],
+    aspects: [usize; ASPECT_BUCKETS.len()],
+}
+
+impl DatasetStats {
+    pub(crate) fn new(image_count: usize) -> DatasetStats {
+        let (request_sender, request_receiver) = crossbeam::channel::unbounded::<ScanRequest>();
+        let (summary_sender, summary_receiver) = crossbeam::channel::unbounded();
+        thread::spawn(move || {
+            while let Ok(request) = request_receiver.recv() {
+                let storage = LabelStorage {
+                    format: request.format,
+                    grid: request.grid,
+                    names: &request.names,
+                };
+                for (index, path) in request.images {
+                    let summary = match ImageFile::new(path) {
+                        Ok(image) => summarize(&image, storage),
+                        Err(err) => {
+                            println!("could not scan image {}: {:?}", index, err);
+                            ImageSummary {
+                                state: None,
+                                boxes: Vec::new(),
+                            }
+                        }
+                    };
+                    if summary_sender.send((index, summary)).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        DatasetStats {
+            images: (0..image_count).map(|_| None).collect(),
+            request_sender,
+            summary_receiver,
+            totals: Totals::default(),
+            dirty: true,
+        }
+    }
+
+    /// reads every label file again
+    pub(crate) fn scan_all(&self, images: &Images, storage: LabelStorage<'_>) {
+        let paths = images
+            .as_slice()
+            .iter()
+            .enumerate()
+            .map(|(i, image)| (i, image.img.as_path()))
+            .collect();
+        self.request(paths, storage);
+    }
+
+    /// reads the label file of the image at `index` again, after it was saved
+    pub(crate) fn scan(&self, index: usize, image: &ImageFile, storage: LabelStorage<'_>) {
+        self.request(vec![(index, image.as_path())], storage);
+    }
+
+    fn request(&self, images: Vec<(usize, PathBuf)>, storage: LabelStorage<'_>) {
+        let request = ScanRequest {
+            images,
+            format: storage.format,
+            grid: storage.grid,
+            names: storage.names.to_vec(),
+        };
+        if self.request_sender.send(request).is_err() {
+            println!("the stats thread has stopped, stats will be out of date");
+        }
+    }
+
+    /// takes in whatever the scan has found since the last call
+    pub(crate) fn update(&mut self) {
+        while let Ok((index, summary)) = self.summary_receiver.try_recv() {
+            if let Some(image) = self.images.get_mut(index) {
+                *image = Some(summary);
+                self.dirty = true;
+            }
+        }
+        if self.dirty {
+            self.totals = Totals::of(&self.images);
+            self.dirty = false;
+        }
+    }
+
+    /// the name of every box in the label file of the image at `index` as of its last scan,
+    /// `None` if it has not been read yet
+    pub(crate) fn box_names(&self, index: usize) -> Option<impl Iterator<Item = usize> + '_> {
+        let summary = self.images.get(index)?.as_ref()?;
+        Some(summary.boxes.iter().map(|bbox| bbox.name))
+    }
+
+    /// whether some label files have not been read yet
+    pub(crate) fn scanning(&self) -> bool {
+        self.totals.scanned < self.images.len()
+    }
+
+    pub(crate) fn display(&self, ui: &mut Ui, names: &[String]) {
+        let totals = &self.totals;
+        if self.scanning() {
+            ui.add(
+                ProgressBar::new(ratio(totals.scanned, self.images.len())).text(format!(
+                    "read {} of {} label files",
+                    totals.scanned,
+                    self.images.len()
+                )),
+            );
+        }
+        ui.heading("images");
+        egui::Grid::new("stats images")
+            .striped(true)
+            .show(ui, |ui| {
+                for (state, count) in &[
+                    ("unvisited", totals.unvisited),
+                    ("reviewed, no boxes", totals.visited_empty),
+                    ("labeled", totals.labeled),
+                    ("could not be opened", totals.unreadable),
+                ] {
+                    ui.label(*state);
+                    ui.label(count.to_string());
+                    ui.end_row();
+                }
+            });
+        ui.heading("classes");
+        let most_boxes = totals.classes.values().map(|(boxes, _)| *boxes).max();
+        egui::Grid::new("stats classes")
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label("name");
+                ui.label("boxes");
+                ui.label("images");
+                ui.end_row();
+                for (name, (boxes, images)) in &totals.classes {
+                    ui.label(
+                        names
+                            .get(*name)
+                            .cloned()
+                            .unwrap_or_else(|| format!("#{}", name)),
+                    );
+                    ui.add(
+                        ProgressBar::new(ratio(*boxes, most_boxes.unwrap_or_default()))
+                            .text(boxes.to_string()),
+                    );
+                    ui.label(images.to_string());
+                    ui.end_row();
+                }
+                for (name, text) in names.iter().enumerate() {
+                    if !totals.classes.contains_key(&name) {
+                        ui.label(text);
+                        ui.label("0");
+                        ui.label("0");
+                        ui.end_row();
+                    }
+                }
+            });
+        ui.heading("box size (side of a square of the same area, share of the image)");
+        histogram(ui, "stats sizes", &SIZE_BUCKETS, &totals.sizes, |bound| {
+            format!("{:.0}%", bound * 100.0)
+        });
+        ui.heading("box aspect ratio (width / height)");
+        histogram(
+            ui,
+            "stats aspects",
+            &ASPECT_BUCKETS,
+            &totals.aspects,
+            |bound| bound.to_string(),
+        );
+    }
+}
+
+/// the same counts as the stats page as text, reading every label file on this thread
+pub(crate) fn report(images: &Images, storage: LabelStorage<'_>) -> String {
+    let summaries = images
+        .as_slice()
+        .iter()
+        .map(|image| Some(summarize(&image.img, storage)))
+        .collect::<Vec<_>>();
+    let totals = Totals::of(&summaries);
+    let mut lines = vec![
+        format!("unvisited: {}", totals.unvisited),
+        format!("reviewed, no boxes: {}", totals.visited_empty),
+        format!("labeled: {}", totals.labeled),
+        String::from("class: boxes, images"),
+    ];
+    for (i, name) in storage.names.iter().enumerate() {
+        let (boxes, images) = totals.classes.get(&i).copied().unwrap_or_default();
+        lines.push(format!("  {}: {}, {}", name, boxes, images));
+    }
+    for (i, (boxes, images)) in totals.classes.range(storage.names.len()..) {
+        lines.push(format!("  #{}: {}, {}", i, boxes, images));
+    }
+    lines.push(String::from("box size:"));
+    for (i, count) in totals.sizes.iter().enumerate() {
+        let label = bucket_label(&SIZE_BUCKETS, i, |bound| format!("{:.0}%", bound * 100.0));
+        lines.push(format!("  {}: {}", label, count));
+    }
+    lines.push(String::from("box aspect ratio:"));
+    for (i, count) in totals.aspects.iter().enumerate() {
+        let label = bucket_label(&ASPECT_BUCKETS, i, |bound| bound.to_string());
+        lines.push(format!("  {}: {}", label, count));
+    }
+    lines.join("\n")
+}
+
+impl Totals {
+    fn of(images: &[Option<ImageSummary>]) -> Totals {
+        let mut totals = Totals::default();
+        for image in images.iter().flatten() {
+            totals.scanned += 1;
+            match image.state {
+                Some(LabelState::Unvisited) => totals.unvisited += 1,
+                Some(LabelState::VisitedEmpty) => totals.visited_empty += 1,
+                Some(LabelState::Labeled) => totals.labeled += 1,
+                None => totals.unreadable += 1,
+            }
+            let mut names = image.boxes.iter().map(|b| b.name).collect::<Vec<_>>();
+            names.sort_unstable();
+            names.dedup();
+            for name in names {
+                totals.classes.entry(name).or_default().1 += 1;
+            }
+            for bbox in &image.boxes {
+                totals.classes.entry(bbox.name).or_default().0 += 1;
+                totals.sizes[bucket(&SIZE_BUCKETS, bbox.size)] += 1;
+                if let Some(aspect) = bbox.aspect {
+                    totals.aspects[bucket(&ASPECT_BUCKETS, aspect)] += 1;
+                }
+            }
+        }
+        totals
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn summarize(image: &ImageFile, storage: LabelStorage<'_>) -> ImageSummary {
+    if !image.has_label_file(storage.format) {
+        return ImageSummary {
+            state: Some(LabelState::Unvisited),
+            boxes: Vec::new(),
+        };
+    }
+    let labels = image.load_stored_labels(storage);
+    let dimensions = image.dimensions(storage.grid).ok();
+    ImageSummary {
+        state: Some(LabelState::of(&labels)),
+        boxes: labels
+            .iter()
+            .map(|bbox| BoxSummary {
+                name: bbox.name,
+                size: (bbox.width * bbox.height).sqrt(),
+                aspect: dimensions
+                    .filter(|_| bbox.height > 0.0)
+                    .map(|(w, h)| (bbox.width * w as f32) / (bbox.height * h as f32)),
+            })
+            .collect(),
+    }
+}
+
+fn bucket(bounds: &[f32], value: f32) -> usize {
+    bounds
+        .iter()
+        .position(|bound| value < *bound)
+        .unwrap_or(bounds.len() - 1)
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn ratio(part: usize, whole: usize) -> f32 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f32 / whole as f32
+    }
+}
+
+fn bucket_label(bounds: &[f32], i: usize, bound_text: impl Fn(f32) -> String) -> String {
+    if bounds[i].is_finite() {
+        format!("< {}", bound_text(bounds[i]))
+    } else {
+        format!(">= {}", bound_text(bounds[i - 1]))
+    }
+}
+
+fn histogram(
+    ui: &mut Ui,
+    id: &str,
+    bounds: &[f32],
+    counts: &[usize],
+    bound_text: impl Fn(f32) -> String,
+) {
+    let most = counts.iter().copied().max().unwrap_or_default();
+    egui::Grid::new(id).striped(true).show(ui, |ui| {
+        for (i, count) in counts.iter().enumerate() {
+            ui.label(bucket_label(bounds, i, &bound_text));
+            ui.add(ProgressBar::new(ratio(*count, most)).text(count.to_string()));
+            ui.end_row();
+        }
+    });
+}
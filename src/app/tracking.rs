@@ -0,0 +1,302 @@
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use crossbeam::channel::{Receiver, Sender};
+use image::imageops::FilterType;
+use image::{imageops, DynamicImage, GrayImage};
+
+use crate::app::bbox::BBox;
+use crate::app::image_file::ImageFile;
+
+/// images are shrunk until their longest side is at most this before tracking
+const MAX_SIDE: u32 = 640;
+/// a template is compared on a grid of at most this many points a side
+const TEMPLATE_SAMPLES: u32 = 32;
+/// how far past its edges a box is looked for, as a share of its longer side
+const SEARCH_MARGIN: f32 = 0.5;
+/// matches scoring less than this are taken to mean the object was lost
+const MIN_SCORE: f32 = 0.5;
+
+/// boxes on the image at `from` to look for on the image at `to`
+struct TrackRequest {
+    from: PathBuf,
+    to: PathBuf,
+    to_index: usize,
+    boxes: Vec<BBox>,
+}
+
+/// Follows boxes from one image to the next on a background thread, so the boxes of a video
+/// frame can be suggested on the frame after it.
+pub struct Tracker {
+    request_sender: Sender<TrackRequest>,
+    suggestion_receiver: Receiver<(usize, Vec<BBox>)>,
+}
+
+impl Tracker {
+    pub(crate) fn new() -> Tracker {
+        let (request_sender, request_receiver) = crossbeam::channel::unbounded::<TrackRequest>();
+        let (suggestion_sender, suggestion_receiver) = crossbeam::channel::unbounded();
+        thread::spawn(move || {
+            while let Ok(mut request) = request_receiver.recv() {
+                // only the image being looked at matters, skip any it has already moved past
+                while let Ok(newer) = request_receiver.try_recv() {
+                    request = newer;
+                }
+                let TrackRequest {
+                    from,
+                    to,
+                    to_index,
+                    boxes,
+                } = request;
+                let suggestions = match decode(&from)
+                    .and_then(|from| decode(&to).map(|to| track(&from, &to, &boxes)))
+                {
+                    Ok(suggestions) => suggestions,
+                    Err(err) => {
+                        println!("could not track boxes into {}: {}", to_index, err);
+                        Vec::new()
+                    }
+                };
+                if suggestion_sender.send((to_index, suggestions)).is_err() {
+                    return;
+                }
+            }
+        });
+        Tracker {
+            request_sender,
+            suggestion_receiver,
+        }
+    }
+
+    /// starts looking for `boxes` of `from` on `to`, the image at `to_index`
+    pub(crate) fn track(
+        &self,
+        from: &ImageFile,
+        to_index: usize,
+        to: &ImageFile,
+        boxes: Vec<BBox>,
+    ) {
+        let request = TrackRequest {
+            from: from.as_path(),
+            to: to.as_path(),
+            to_index,
+            boxes,
+        };
+        if self.request_sender.send(request).is_err() {
+            println!("the tracking thread has stopped, boxes will not be tracked");
+        }
+    }
+
+    /// the index of an image and the boxes found on it, if tracking into it has finished
+    pub(crate) fn suggestions(&self) -> Option<(usize, Vec<BBox>)> {
+        self.suggestion_receiver.try_recv().ok()
+    }
+}
+
+fn decode(path: &Path) -> Result<DynamicImage, String> {
+    ImageFile::new(path.to_path_buf())
+        .map_err(|err| format!("{:?}", err))?
+        .as_image()
+        .map_err(|err| err.to_string())
+}
+
+/// Where each of `boxes` on `from` moved to on `to`, found by matching what is inside the box
+/// around where it was. Boxes that cannot be found are left out, the rest keep their size, name
+/// and flags.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+pub(crate) fn track(from: &DynamicImage, to: &DynamicImage, boxes: &[BBox]) -> Vec<BBox> {
+    let from = from.to_luma8();
+    let longest = from.width().max(from.height());
+    let from = if longest > MAX_SIDE {
+        let scale = MAX_SIDE as f32 / longest as f32;
+        imageops::resize(
+            &from,
+            ((from.width() as f32 * scale).round() as u32).max(1),
+            ((from.height() as f32 * scale).round() as u32).max(1),
+            FilterType::Triangle,
+        )
+    } else {
+        from
+    };
+    // boxes are relative to the image, so stretching `to` onto `from` keeps them lined up
+    let to = imageops::resize(
+        &to.to_luma8(),
+        from.width(),
+        from.height(),
+        FilterType::Triangle,
+    );
+    boxes
+        .iter()
+        .filter_map(|bbox| track_box(&from, &to, bbox))
+        .collect()
+}
+
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+fn track_box(from: &GrayImage, to: &GrayImage, bbox: &BBox) -> Option<BBox> {
+    let (w, h) = from.dimensions();
+    let width = ((bbox.width * w as f32).round() as u32).min(w);
+    let height = ((bbox.height * h as f32).round() as u32).min(h);
+    // too small to tell apart from its surroundings
+    if width < 4 || height < 4 {
+        return None;
+    }
+    let left = (((bbox.x - bbox.width / 2.0) * w as f32).round().max(0.0) as u32).min(w - width);
+    let top = (((bbox.y - bbox.height / 2.0) * h as f32).round().max(0.0) as u32).min(h - height);
+    let template = Template::new(from, left, top, width, height)?;
+    let margin = (width.max(height) as f32 * SEARCH_MARGIN) as u32 + template.step;
+    let xs = left.saturating_sub(margin)..=(left + margin).min(w - width);
+    let ys = top.saturating_sub(margin)..=(top + margin).min(h - height);
+    // every `step` pixels first, then every pixel around the best of those
+    let step = template.step as usize;
+    let coarse = best_match(
+        &template,
+        to,
+        &xs.clone().step_by(step),
+        ys.clone().step_by(step),
+    )?;
+    let near_columns = coarse.1.saturating_sub(template.step).max(*xs.start())
+        ..=(coarse.1 + template.step).min(*xs.end());
+    let near_rows = coarse.2.saturating_sub(template.step).max(*ys.start())
+        ..=(coarse.2 + template.step).min(*ys.end());
+    let (score, x, y) = best_match(&template, to, &near_columns, near_rows)?;
+    if score < MIN_SCORE {
+        return None;
+    }
+    let center_x = (x as f32 + width as f32 / 2.0) / w as f32;
+    let center_y = (y as f32 + height as f32 / 2.0) / h as f32;
+    BBox::new(
+        bbox.name,
+        bbox.width,
+        bbox.height,
+        center_x.clamp(bbox.width / 2.0, 1.0 - bbox.width / 2.0),
+        center_y.clamp(bbox.height / 2.0, 1.0 - bbox.height / 2.0),
+    )
+    .ok()
+    .map(|tracked| tracked.with_flags_of(bbox))
+}
+
+/// the inside of a box, sampled every `step` pixels
+struct Template {
+    step: u32,
+    /// offsets from the top left corner and how far the pixel there is from the mean
+    samples: Vec<(u32, u32, f32)>,
+    /// the sum of the squares of the differences from the mean
+    variance: f32,
+}
+
+impl Template {
+    /// `None` for a box of one flat colour, which matches anywhere
+    fn new(image: &GrayImage, left: u32, top: u32, width: u32, height: u32) -> Option<Template> {
+        let step = width.max(height).div_ceil(TEMPLATE_SAMPLES);
+        let mut samples = Vec::new();
+        for dy in (0..height).step_by(step as usize) {
+            for dx in (0..width).step_by(step as usize) {
+                let value = f32::from(image.get_pixel(left + dx, top + dy)[0]);
+                samples.push((dx, dy, value));
+            }
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let mean = samples.iter().map(|(_, _, value)| value).sum::<f32>() / samples.len() as f32;
+        for sample in &mut samples {
+            sample.2 -= mean;
+        }
+        let variance = samples
+            .iter()
+            .map(|(_, _, value)| value * value)
+            .sum::<f32>();
+        (variance > f32::EPSILON).then_some(Template {
+            step,
+            samples,
+            variance,
+        })
+    }
+
+    /// the normalized cross correlation of the template with `image` at `x`, `y`, from -1 to 1
+    #[allow(clippy::cast_precision_loss)]
+    fn score(&self, image: &GrayImage, x: u32, y: u32) -> f32 {
+        let (mut sum, mut sum_squares, mut product) = (0.0, 0.0, 0.0);
+        for (dx, dy, value) in &self.samples {
+            let pixel = f32::from(image.get_pixel(x + dx, y + dy)[0]);
+            sum += pixel;
+            sum_squares += pixel * pixel;
+            product += pixel * value;
+        }
+        let variance = sum_squares - sum * sum / self.samples.len() as f32;
+        if variance <= f32::EPSILON {
+            0.0
+        } else {
+            product / (variance * self.variance).sqrt()
+        }
+    }
+}
+
+/// the best score and where it is
+fn best_match(
+    template: &Template,
+    image: &GrayImage,
+    xs: &(impl Iterator<Item = u32> + Clone),
+    ys: impl Iterator<Item = u32>,
+) -> Option<(f32, u32, u32)> {
+    ys.flat_map(|y| xs.clone().map(move |x| (x, y)))
+        .map(|(x, y)| (template.score(image, x, y), x, y))
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a few soft blobs, shifted right by `dx` and down by `dy`
+    #[allow(clippy::cast_precision_loss)]
+    fn blobs(dx: f32, dy: f32) -> DynamicImage {
+        let centers = [(40.0, 30.0), (90.0, 70.0), (150.0, 40.0), (60.0, 110.0)];
+        DynamicImage::ImageLuma8(GrayImage::from_fn(200, 150, |x, y| {
+            let value = centers
+                .iter()
+                .map(|(cx, cy)| {
+                    let (ox, oy) = (x as f32 - cx - dx, y as f32 - cy - dy);
+                    200.0 * (-(ox * ox + oy * oy) / 300.0).exp()
+                })
+                .sum::<f32>();
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            image::Luma([value.min(255.0) as u8])
+        }))
+    }
+
+    #[test]
+    fn follows_a_box_that_moved() {
+        let bbox = BBox::new(1, 0.2, 0.2, 90.0 / 200.0, 70.0 / 150.0).unwrap();
+        let tracked = track(&blobs(0.0, 0.0), &blobs(12.0, -7.0), &[bbox.clone()]);
+        assert_eq!(tracked.len(), 1);
+        let moved = &tracked[0];
+        assert_eq!(
+            (moved.name, moved.width, moved.height),
+            (1, bbox.width, bbox.height)
+        );
+        assert!(
+            (moved.x * 200.0 - 102.0).abs() <= 1.0,
+            "x is {}",
+            moved.x * 200.0
+        );
+        assert!(
+            (moved.y * 150.0 - 63.0).abs() <= 1.0,
+            "y is {}",
+            moved.y * 150.0
+        );
+    }
+
+    #[test]
+    fn drops_a_box_that_cannot_be_found() {
+        let bbox = BBox::new(0, 0.2, 0.2, 90.0 / 200.0, 70.0 / 150.0).unwrap();
+        let flat = DynamicImage::ImageLuma8(GrayImage::from_pixel(200, 150, image::Luma([90])));
+        assert!(track(&blobs(0.0, 0.0), &flat, &[bbox]).is_empty());
+    }
+}
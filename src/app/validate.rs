@@ -0,0 +1,420 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::app::bbox::BBox;
+use crate::app::image_file::{ImageFile, LabelFormat, LabelStorage};
+use crate::app::images::Images;
+use crate::app::voc;
+
+#[derive(serde::Serialize, Copy, Clone, Debug, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProblemKind {
+    /// the file could not be read or parsed at all
+    Unreadable,
+    /// a line or object that loading skips because it cannot be parsed
+    Malformed,
+    /// a class id past the end of the names file, or a VOC name that is not in it
+    UnknownClass,
+    NonPositiveSize,
+    /// a box that loading skips because it is not on the image
+    OutOfRange,
+    /// a box that loading quietly shrinks to fit on the image, the next save writes the change
+    Clamped,
+    Duplicate,
+    /// a label file with no image next to it
+    NoImage,
+}
+
+/// Something wrong with a label file that loading it would skip over or quietly change.
+#[derive(serde::Serialize, Debug)]
+pub struct Problem {
+    pub file: PathBuf,
+    /// counted from 1, darknet files only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    /// counted from 1, VOC files only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub object: Option<usize>,
+    pub kind: ProblemKind,
+    pub message: String,
+}
+
+/// a box as it is written in a label file, before `BBox::new` checks and clamps it
+struct RawBox {
+    /// where the box is in the file, a line for darknet and an object for VOC
+    position: usize,
+    /// the class id or name as written
+    class: String,
+    /// `None` if the class is not in the names file
+    name: Option<usize>,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+}
+
+/// every problem in the label files of `images`, in the order of the images, followed by label
+/// files that have no image
+pub(crate) fn validate(images: &Images, storage: LabelStorage<'_>) -> Vec<Problem> {
+    let mut problems = images
+        .as_slice()
+        .iter()
+        .filter(|image| image.img.has_label_file(storage.format))
+        .flat_map(|image| match storage.format {
+            LabelFormat::Darknet => validate_darknet(&image.img, storage.names),
+            LabelFormat::Voc => validate_voc(&image.img, storage),
+        })
+        .collect::<Vec<_>>();
+    problems.extend(labels_without_images(images, storage.format));
+    problems
+}
+
+fn validate_darknet(image: &ImageFile, names: &[String]) -> Vec<Problem> {
+    let file = image.label_path(LabelFormat::Darknet);
+    let contents = match fs::read_to_string(&file) {
+        Ok(contents) => contents,
+        Err(err) => {
+            return vec![Problem {
+                file,
+                line: None,
+                object: None,
+                kind: ProblemKind::Unreadable,
+                message: err.to_string(),
+            }]
+        }
+    };
+    let mut problems = Vec::new();
+    let mut boxes = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        if line.is_empty() {
+            continue;
+        }
+        match parse_darknet_line(line) {
+            Ok((name, x, y, width, height)) => boxes.push(RawBox {
+                position: i + 1,
+                class: name.to_string(),
+                name: (name < names.len()).then_some(name),
+                x,
+                y,
+                width,
+                height,
+            }),
+            Err(message) => problems.push(Problem {
+                file: file.clone(),
+                line: Some(i + 1),
+                object: None,
+                kind: ProblemKind::Malformed,
+                message,
+            }),
+        }
+    }
+    problems.extend(check_boxes(&boxes, "line", |position, kind, message| {
+        Problem {
+            file: file.clone(),
+            line: Some(position),
+            object: None,
+            kind,
+            message,
+        }
+    }));
+    problems.sort_by_key(|problem| problem.line);
+    problems
+}
+
+/// the fields of a darknet line, split the same way `BBox::try_from` does
+fn parse_darknet_line(line: &str) -> Result<(usize, f32, f32, f32, f32), String> {
+    if let [name, x, y, width, height] = line.split(' ').collect::<Vec<_>>().as_slice() {
+        let float = |value: &str| {
+            value
+                .parse::<f32>()
+                .map_err(|err| format!("{} is not a number: {}", value, err))
+        };
+        Ok((
+            name.parse()
+                .map_err(|err| format!("{} is not a class id: {}", name, err))?,
+            float(x)?,
+            float(y)?,
+            float(width)?,
+            float(height)?,
+        ))
+    } else {
+        Err(format!(
+            "expected 5 values separated by single spaces in {:?}",
+            line
+        ))
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn validate_voc(image: &ImageFile, storage: LabelStorage<'_>) -> Vec<Problem> {
+    let file = image.label_path(LabelFormat::Voc);
+    let problem = |object: Option<usize>, kind: ProblemKind, message: String| Problem {
+        file: file.clone(),
+        line: None,
+        object,
+        kind,
+        message,
+    };
+    let objects = match voc::read_objects(&file) {
+        Ok(objects) => objects,
+        Err(err) => return vec![problem(None, ProblemKind::Unreadable, err.to_string())],
+    };
+    let (w, h) = match image.dimensions(storage.grid) {
+        Ok((w, h)) => (w as f32, h as f32),
+        Err(err) => {
+            let message = format!("the size of the image could not be read: {}", err);
+            return vec![problem(None, ProblemKind::Unreadable, message)];
+        }
+    };
+    let mut problems = Vec::new();
+    let mut boxes = Vec::new();
+    for (i, object) in objects.into_iter().enumerate() {
+        match object {
+            Ok(object) => {
+                let [xmin, ymin, xmax, ymax] = object.bndbox;
+                boxes.push(RawBox {
+                    position: i + 1,
+                    name: storage.names.iter().position(|name| *name == object.name),
+                    class: object.name,
+                    x: (xmin + (xmax - xmin) / 2.0) / w,
+                    y: (ymin + (ymax - ymin) / 2.0) / h,
+                    width: (xmax - xmin) / w,
+                    height: (ymax - ymin) / h,
+                });
+            }
+            Err(err) => problems.push(problem(
+                Some(i + 1),
+                ProblemKind::Malformed,
+                err.to_string(),
+            )),
+        }
+    }
+    problems.extend(check_boxes(&boxes, "object", |position, kind, message| {
+        problem(Some(position), kind, message)
+    }));
+    problems.sort_by_key(|problem| problem.object);
+    problems
+}
+
+/// the problems with boxes that parsed, `problem` says where they are and `position` is what
+/// a position in the file is called
+fn check_boxes(
+    boxes: &[RawBox],
+    position: &str,
+    problem: impl Fn(usize, ProblemKind, String) -> Problem,
+) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    for (i, raw) in boxes.iter().enumerate() {
+        if raw.name.is_none() {
+            problems.push(problem(
+                raw.position,
+                ProblemKind::UnknownClass,
+                format!("class {} is not in the names file", raw.class),
+            ));
+        }
+        if raw.width <= 0.0 || raw.height <= 0.0 {
+            problems.push(problem(
+                raw.position,
+                ProblemKind::NonPositiveSize,
+                format!("the box is {} by {}", raw.width, raw.height),
+            ));
+            continue;
+        }
+        match BBox::new(
+            raw.name.unwrap_or_default(),
+            raw.width,
+            raw.height,
+            raw.x,
+            raw.y,
+        ) {
+            Err(err) => problems.push(problem(
+                raw.position,
+                ProblemKind::OutOfRange,
+                format!("{}, loading skips the box", err),
+            )),
+            Ok(bbox) if !same_place(&bbox, raw) => problems.push(problem(
+                raw.position,
+                ProblemKind::Clamped,
+                format!(
+                    "the box goes past the edge of the image, loading clamps it to {} {} {} {}",
+                    bbox.x, bbox.y, bbox.width, bbox.height
+                ),
+            )),
+            Ok(_) => {}
+        }
+        // unknown classes are told apart by how they are written, not all as `None`
+        let same_class = |other: &RawBox| {
+            other.class == raw.class || (raw.name.is_some() && other.name == raw.name)
+        };
+        if let Some(original) = boxes[..i]
+            .iter()
+            .find(|other| same_class(other) && same_place_raw(other, raw))
+        {
+            problems.push(problem(
+                raw.position,
+                ProblemKind::Duplicate,
+                format!("the same box as {} {}", position, original.position),
+            ));
+        }
+    }
+    problems
+}
+
+fn close(a: f32, b: f32) -> bool {
+    (a - b).abs() < 1e-6
+}
+
+fn same_place(bbox: &BBox, raw: &RawBox) -> bool {
+    close(bbox.x, raw.x)
+        && close(bbox.y, raw.y)
+        && close(bbox.width, raw.width)
+        && close(bbox.height, raw.height)
+}
+
+fn same_place_raw(a: &RawBox, b: &RawBox) -> bool {
+    close(a.x, b.x) && close(a.y, b.y) && close(a.width, b.width) && close(a.height, b.height)
+}
+
+/// label files in the directories of `images` that do not belong to any of them
+fn labels_without_images(images: &Images, format: LabelFormat) -> Vec<Problem> {
+    let label_paths = images
+        .as_slice()
+        .iter()
+        .map(|image| image.img.label_path(format))
+        .collect::<BTreeSet<_>>();
+    let directories = images
+        .as_slice()
+        .iter()
+        .filter_map(|image| image.img.as_path().parent().map(Path::to_path_buf))
+        .collect::<BTreeSet<_>>();
+    let extension = match format {
+        LabelFormat::Darknet => "txt",
+        LabelFormat::Voc => "xml",
+    };
+    directories
+        .iter()
+        .flat_map(|dir| fs::read_dir(dir).into_iter().flatten().flatten())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension().and_then(|it| it.to_str()) == Some(extension)
+                && !label_paths.contains(path)
+                && looks_like_labels(path, format)
+        })
+        .map(|file| Problem {
+            file,
+            line: None,
+            object: None,
+            kind: ProblemKind::NoImage,
+            message: String::from("there is no image for this label file"),
+        })
+        .collect()
+}
+
+/// whether `path` is a label file rather than a names file, an image list or notes that happen
+/// to sit next to the images
+fn looks_like_labels(path: &Path, format: LabelFormat) -> bool {
+    match format {
+        // empty label files are left out, they are harmless and so is any other empty file
+        LabelFormat::Darknet => fs::read_to_string(path).is_ok_and(|contents| {
+            let mut lines = contents.lines().filter(|line| !line.is_empty()).peekable();
+            lines.peek().is_some() && lines.all(|line| parse_darknet_line(line).is_ok())
+        }),
+        LabelFormat::Voc => {
+            fs::read_to_string(path).is_ok_and(|contents| contents.contains("<annotation"))
+        }
+    }
+}
+
+/// one problem per line, as `file:line: message`
+pub(crate) fn report(problems: &[Problem]) -> String {
+    let lines = problems
+        .iter()
+        .map(|problem| match (problem.line, problem.object) {
+            (Some(line), _) => format!("{}:{}: {}", problem.file.display(), line, problem.message),
+            (None, Some(object)) => format!(
+                "{}: object {}: {}",
+                problem.file.display(),
+                object,
+                problem.message
+            ),
+            (None, None) => format!("{}: {}", problem.file.display(), problem.message),
+        })
+        .collect::<Vec<_>>();
+    format!("{}\n{} problems found", lines.join("\n"), problems.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw(position: usize, class: &str, name: Option<usize>, x: f32, width: f32) -> RawBox {
+        RawBox {
+            position,
+            class: class.to_string(),
+            name,
+            x,
+            y: 0.5,
+            width,
+            height: 0.2,
+        }
+    }
+
+    fn kinds(boxes: &[RawBox]) -> Vec<(usize, ProblemKind)> {
+        check_boxes(boxes, "line", |position, kind, message| Problem {
+            file: PathBuf::new(),
+            line: Some(position),
+            object: None,
+            kind,
+            message,
+        })
+        .iter()
+        .map(|problem| (problem.line.unwrap(), problem.kind))
+        .collect()
+    }
+
+    #[test]
+    fn reports_duplicates_of_the_same_class() {
+        let boxes = [
+            raw(1, "0", Some(0), 0.5, 0.2),
+            raw(2, "1", Some(1), 0.5, 0.2),
+            raw(3, "0", Some(0), 0.5, 0.2),
+        ];
+        assert_eq!(kinds(&boxes), vec![(3, ProblemKind::Duplicate)]);
+    }
+
+    #[test]
+    fn unknown_classes_are_told_apart() {
+        let boxes = [
+            raw(1, "5", None, 0.5, 0.2),
+            raw(2, "6", None, 0.5, 0.2),
+            raw(3, "5", None, 0.5, 0.2),
+        ];
+        assert_eq!(
+            kinds(&boxes),
+            vec![
+                (1, ProblemKind::UnknownClass),
+                (2, ProblemKind::UnknownClass),
+                (3, ProblemKind::UnknownClass),
+                (3, ProblemKind::Duplicate),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_boxes_off_and_past_the_edge() {
+        let boxes = [
+            raw(1, "0", Some(0), 1.5, 0.2),
+            raw(2, "0", Some(0), 0.95, 0.2),
+            raw(3, "0", Some(0), 0.5, 0.0),
+        ];
+        assert_eq!(
+            kinds(&boxes),
+            vec![
+                (1, ProblemKind::OutOfRange),
+                (2, ProblemKind::Clamped),
+                (3, ProblemKind::NonPositiveSize),
+            ]
+        );
+    }
+}
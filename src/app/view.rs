@@ -0,0 +1,73 @@
+use std::time::{Duration, Instant};
+
+use eframe::egui::{Pos2, Rect, Vec2};
+
+use crate::app::image_cache::Region;
+
+/// The part of the image that is on screen, in normalized image coordinates.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct View {
+    zoom: f32,
+    center: Pos2,
+    changed: Instant,
+}
+
+impl Default for View {
+    fn default() -> Self {
+        View {
+            zoom: 1.0,
+            center: Pos2 { x: 0.5, y: 0.5 },
+            changed: Instant::now(),
+        }
+    }
+}
+
+impl View {
+    const MAX_ZOOM: f32 = 32.0;
+    // how long the view has to sit still before we bother decoding a sharper crop of it
+    const SETTLE_TIME: Duration = Duration::from_millis(150);
+
+    /// the visible part of the image
+    pub(crate) fn visible(&self) -> Rect {
+        Rect::from_center_size(self.center, Vec2::splat(1.0 / self.zoom))
+    }
+
+    /// where the whole image ends up when the visible part of it is stretched over `screen`.
+    /// Boxes are drawn and created relative to this rather than the screen rect.
+    pub(crate) fn image_rect(&self, screen: Rect) -> Rect {
+        let visible = self.visible();
+        let size = screen.size() / visible.size();
+        Rect::from_min_size(screen.min - visible.min.to_vec2() * size, size)
+    }
+
+    /// zooms by `factor` keeping the part of the image under `pointer` where it is
+    pub(crate) fn zoom_around(&mut self, factor: f32, screen: Rect, pointer: Pos2) {
+        let visible = self.visible();
+        let on_screen = (pointer - screen.min) / screen.size();
+        let under_pointer = visible.min + on_screen * visible.size();
+        self.zoom = (self.zoom * factor).clamp(1.0, Self::MAX_ZOOM);
+        let size = Vec2::splat(1.0 / self.zoom);
+        self.center = under_pointer - on_screen * size + size / 2.0;
+        self.clamp_center();
+    }
+
+    /// moves the image by `delta` screen points
+    pub(crate) fn pan(&mut self, delta: Vec2, screen: Rect) {
+        self.center -= delta / screen.size() / self.zoom;
+        self.clamp_center();
+    }
+
+    fn clamp_center(&mut self) {
+        let half = 0.5 / self.zoom;
+        self.center.x = self.center.x.clamp(half, 1.0 - half);
+        self.center.y = self.center.y.clamp(half, 1.0 - half);
+        self.changed = Instant::now();
+    }
+
+    /// the region worth decoding at a higher resolution, `None` if the whole image is visible or
+    /// the view is still moving.
+    pub(crate) fn region(&self) -> Option<Region> {
+        (self.zoom > 1.0 && self.changed.elapsed() > Self::SETTLE_TIME)
+            .then(|| Region::from_rect(self.visible()))
+    }
+}
@@ -0,0 +1,350 @@
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufReader, ErrorKind};
+use std::path::Path;
+
+use xmltree::{Element, EmitterConfig, ParseError, XMLNode};
+
+use crate::app::atomic_file;
+use crate::app::bbox::{BBox, BBoxError};
+
+#[derive(Debug)]
+pub enum VocError {
+    Io(std::io::Error),
+    Parse(ParseError),
+    Write(xmltree::Error),
+    InvalidObject(String),
+    BBox(BBoxError),
+}
+
+impl Display for VocError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            VocError::Io(err) => write!(f, "Io {}", err),
+            VocError::Parse(err) => write!(f, "Parse {}", err),
+            VocError::Write(err) => write!(f, "Write {}", err),
+            VocError::InvalidObject(explanation) => write!(f, "InvalidObject {}", explanation),
+            VocError::BBox(err) => write!(f, "BBox {}", err),
+        }
+    }
+}
+
+impl From<std::io::Error> for VocError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<ParseError> for VocError {
+    fn from(err: ParseError) -> Self {
+        Self::Parse(err)
+    }
+}
+
+impl From<xmltree::Error> for VocError {
+    fn from(err: xmltree::Error) -> Self {
+        Self::Write(err)
+    }
+}
+
+impl From<BBoxError> for VocError {
+    fn from(err: BBoxError) -> Self {
+        Self::BBox(err)
+    }
+}
+
+impl Error for VocError {}
+
+/// An object of a VOC file as it is written, before it is checked against the names file or the
+/// size of the image.
+pub(crate) struct VocObject {
+    pub name: String,
+    /// xmin, ymin, xmax, ymax in pixels
+    pub bndbox: [f32; 4],
+    pub difficult: bool,
+    pub truncated: bool,
+}
+
+impl VocObject {
+    #[allow(clippy::cast_precision_loss)]
+    fn to_bbox(&self, name: usize, (width, height): (u32, u32)) -> Result<BBox, VocError> {
+        let (w, h) = (width as f32, height as f32);
+        let [xmin, ymin, xmax, ymax] = self.bndbox;
+        let (box_w, box_h) = (xmax - xmin, ymax - ymin);
+        let mut bbox = BBox::new(
+            name,
+            box_w / w,
+            box_h / h,
+            (xmin + box_w / 2.0) / w,
+            (ymin + box_h / 2.0) / h,
+        )?;
+        bbox.difficult = self.difficult;
+        bbox.truncated = self.truncated;
+        Ok(bbox)
+    }
+}
+
+/// Reads the objects in the VOC file at `path` without checking them.
+pub(crate) fn read_objects(path: &Path) -> Result<Vec<Result<VocObject, VocError>>, VocError> {
+    let annotation = Element::parse(BufReader::new(File::open(path)?))?;
+    Ok(objects(&annotation).map(read_object).collect())
+}
+
+/// Reads the objects in the VOC file at `path` as boxes on an image of `size` pixels.
+///
+/// Objects with a name that is not in `names` are skipped, they are left in the file when it is
+/// saved again.
+pub(crate) fn load(
+    path: &Path,
+    names: &[String],
+    size: (u32, u32),
+) -> Result<Vec<Result<BBox, VocError>>, VocError> {
+    Ok(read_objects(path)?
+        .into_iter()
+        .map(|object| {
+            let object = object?;
+            match names.iter().position(|it| *it == object.name) {
+                Some(name) => object.to_bbox(name, size),
+                None => Err(VocError::InvalidObject(format!(
+                    "{} is not in the names file",
+                    object.name
+                ))),
+            }
+        })
+        .collect())
+}
+
+/// Writes `labels` to the VOC file at `path` for the image `file_name` of `size` pixels.
+///
+/// If the file already exists it is annotated in place. Objects that load as one of `labels` are
+/// kept exactly as they were, along with their pose, occlusion, attributes and parts. An object
+/// whose box was edited keeps all of that too, only its name, bndbox, truncated and difficult are
+/// rewritten. Objects that do not load (unknown names, broken boxes) are never touched, the only
+/// objects removed are boxes that were deleted.
+pub(crate) fn save(
+    path: &Path,
+    file_name: &str,
+    names: &[String],
+    size: (u32, u32),
+    labels: &[BBox],
+) -> Result<(), VocError> {
+    let mut annotation = match File::open(path) {
+        Ok(f) => Element::parse(BufReader::new(f))?,
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            let mut annotation = Element::new("annotation");
+            set_child_text(&mut annotation, "filename", file_name);
+            child_mut(&mut annotation, "size");
+            set_child_text(&mut annotation, "segmented", "0");
+            annotation
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let size_element = child_mut(&mut annotation, "size");
+    set_child_text(size_element, "width", &size.0.to_string());
+    set_child_text(size_element, "height", &size.1.to_string());
+    if size_element.get_child("depth").is_none() {
+        set_child_text(size_element, "depth", "3");
+    }
+    let name_of = |bbox: &BBox| {
+        names
+            .get(bbox.name)
+            .cloned()
+            .unwrap_or_else(|| bbox.name.to_string())
+    };
+    let mut remaining = labels.iter().collect::<Vec<_>>();
+    let mut edited = Vec::new();
+    for (i, node) in annotation.children.iter().enumerate() {
+        let loaded = node
+            .as_element()
+            .filter(|element| element.name == "object")
+            .and_then(|object| load_object(object, names, size));
+        if let Some(loaded) = loaded {
+            match remaining.iter().position(|label| same_box(label, &loaded)) {
+                Some(j) => {
+                    remaining.remove(j);
+                }
+                None => edited.push((i, loaded)),
+            }
+        }
+    }
+    // what is left of `labels` was moved, resized or relabeled, or is new. An edited box goes
+    // back into the object it overlaps most, objects that overlap nothing were deleted.
+    let mut deleted = Vec::new();
+    for (i, loaded) in edited {
+        let closest = remaining
+            .iter()
+            .enumerate()
+            .map(|(j, label)| (j, overlap(label, &loaded)))
+            .filter(|(_, overlap)| *overlap > 0.0)
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        match (closest, annotation.children[i].as_mut_element()) {
+            (Some((j, _)), Some(object)) => {
+                let label = remaining.remove(j);
+                write_box(object, label, &name_of(label), size);
+            }
+            _ => deleted.push(i),
+        }
+    }
+    for i in deleted.into_iter().rev() {
+        annotation.children.remove(i);
+    }
+    for bbox in remaining {
+        annotation
+            .children
+            .push(XMLNode::Element(bbox_to_object(bbox, &name_of(bbox), size)));
+    }
+    let mut xml = Vec::new();
+    annotation.write_with_config(&mut xml, EmitterConfig::new().perform_indent(true))?;
+    Ok(atomic_file::write(path, &xml)?)
+}
+
+/// The VOC file `contents` with each object renamed by `rename`, and how many objects were
+/// removed because `rename` gave `None` for them. Nothing else about an object is changed.
+pub(crate) fn rename_objects(
+    contents: &[u8],
+    rename: impl Fn(&str) -> Option<String>,
+) -> Result<(Vec<u8>, usize), VocError> {
+    let mut annotation = Element::parse(contents)?;
+    let mut renamed = false;
+    let mut removed = Vec::new();
+    for (i, node) in annotation.children.iter_mut().enumerate() {
+        let object = match node.as_mut_element() {
+            Some(element) if element.name == "object" => element,
+            _ => continue,
+        };
+        if let Ok(name) = child_text(object, "name") {
+            match rename(&name) {
+                Some(new) if new != name => {
+                    set_child_text(object, "name", &new);
+                    renamed = true;
+                }
+                Some(_) => {}
+                None => removed.push(i),
+            }
+        }
+    }
+    // left byte for byte as it was when no object changed
+    if !renamed && removed.is_empty() {
+        return Ok((contents.to_vec(), 0));
+    }
+    for i in removed.iter().rev() {
+        annotation.children.remove(*i);
+    }
+    let mut xml = Vec::new();
+    annotation.write_with_config(&mut xml, EmitterConfig::new().perform_indent(true))?;
+    Ok((xml, removed.len()))
+}
+
+/// the box `object` loads as, `None` if loading skips it
+fn load_object(object: &Element, names: &[String], size: (u32, u32)) -> Option<BBox> {
+    let object = read_object(object).ok()?;
+    let name = names.iter().position(|it| *it == object.name)?;
+    object.to_bbox(name, size).ok()
+}
+
+/// whether `a` and `b` are the same box, allowing for the rounding of moving a box between the
+/// raw and the oriented image
+fn same_box(a: &BBox, b: &BBox) -> bool {
+    let close = |a: f32, b: f32| (a - b).abs() < 1e-4;
+    a.name == b.name
+        && a.difficult == b.difficult
+        && a.truncated == b.truncated
+        && close(a.x, b.x)
+        && close(a.y, b.y)
+        && close(a.width, b.width)
+        && close(a.height, b.height)
+}
+
+/// the area `a` and `b` have in common
+fn overlap(a: &BBox, b: &BBox) -> f32 {
+    let side = |a_center: f32, a_size: f32, b_center: f32, b_size: f32| {
+        let min = (a_center - a_size / 2.0).max(b_center - b_size / 2.0);
+        let max = (a_center + a_size / 2.0).min(b_center + b_size / 2.0);
+        (max - min).max(0.0)
+    };
+    side(a.x, a.width, b.x, b.width) * side(a.y, a.height, b.y, b.height)
+}
+
+fn objects(annotation: &Element) -> impl Iterator<Item = &Element> {
+    annotation
+        .children
+        .iter()
+        .filter_map(XMLNode::as_element)
+        .filter(|element| element.name == "object")
+}
+
+fn child_text(element: &Element, name: &str) -> Result<String, VocError> {
+    element
+        .get_child(name)
+        .and_then(Element::get_text)
+        .map(|text| text.trim().to_string())
+        .ok_or_else(|| VocError::InvalidObject(format!("missing {} in {}", name, element.name)))
+}
+
+/// the first child of `element` called `name`, added to the end if there is none
+fn child_mut<'a>(element: &'a mut Element, name: &str) -> &'a mut Element {
+    if element.get_child(name).is_none() {
+        element.children.push(XMLNode::Element(Element::new(name)));
+    }
+    element
+        .get_mut_child(name)
+        .expect("the child was just added")
+}
+
+fn set_child_text(element: &mut Element, name: &str, text: &str) {
+    child_mut(element, name).children = vec![XMLNode::Text(text.to_string())];
+}
+
+fn flag(object: &Element, name: &str) -> bool {
+    matches!(child_text(object, name).as_deref(), Ok("1" | "true"))
+}
+
+fn read_object(object: &Element) -> Result<VocObject, VocError> {
+    let bndbox = object
+        .get_child("bndbox")
+        .ok_or_else(|| VocError::InvalidObject(String::from("missing bndbox in object")))?;
+    let coord = |name: &str| -> Result<f32, VocError> {
+        child_text(bndbox, name)?
+            .parse::<f32>()
+            .map_err(|err| VocError::BBox(BBoxError::ParseFloatError(err)))
+    };
+    Ok(VocObject {
+        name: child_text(object, "name")?,
+        bndbox: [
+            coord("xmin")?,
+            coord("ymin")?,
+            coord("xmax")?,
+            coord("ymax")?,
+        ],
+        difficult: flag(object, "difficult"),
+        truncated: flag(object, "truncated"),
+    })
+}
+
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+fn write_box(object: &mut Element, bbox: &BBox, name: &str, (width, height): (u32, u32)) {
+    let (w, h) = (width as f32, height as f32);
+    let pixel = |v: f32, size: f32| ((v * size).round() as u32).to_string();
+    set_child_text(object, "name", name);
+    set_child_text(object, "truncated", if bbox.truncated { "1" } else { "0" });
+    set_child_text(object, "difficult", if bbox.difficult { "1" } else { "0" });
+    let bndbox = child_mut(object, "bndbox");
+    set_child_text(bndbox, "xmin", &pixel(bbox.x - bbox.width / 2.0, w));
+    set_child_text(bndbox, "ymin", &pixel(bbox.y - bbox.height / 2.0, h));
+    set_child_text(bndbox, "xmax", &pixel(bbox.x + bbox.width / 2.0, w));
+    set_child_text(bndbox, "ymax", &pixel(bbox.y + bbox.height / 2.0, h));
+}
+
+fn bbox_to_object(bbox: &BBox, name: &str, size: (u32, u32)) -> Element {
+    let mut object = Element::new("object");
+    set_child_text(&mut object, "name", name);
+    set_child_text(&mut object, "pose", "Unspecified");
+    write_box(&mut object, bbox, name, size);
+    object
+}
@@ -2,19 +2,35 @@
 #![cfg_attr(not(debug_assertions), deny(warnings))]
 #![warn(clippy::pedantic, rust_2018_idioms)]
 
-use std::env;
+use std::{env, process};
 
 use yolo_mark_rs::{wrangle_args, KeyboardMapping, RsMark};
 
 fn main() {
     match wrangle_args(env::args()) {
-        Ok(args) => {
-            let app = RsMark::yolo(args, KeyboardMapping::default());
-            let native_options = eframe::NativeOptions::default();
-            eframe::run_native(Box::new(app), native_options);
-        }
+        Ok(args) => match &args.command {
+            Some(command) => match command.run(&args) {
+                Ok(summary) => println!("{}", summary),
+                Err(err) => {
+                    println!("{}", err);
+                    process::exit(1);
+                }
+            },
+            None => match KeyboardMapping::from_file() {
+                Ok(key_map) => {
+                    let app = RsMark::yolo(args, key_map);
+                    let native_options = eframe::NativeOptions::default();
+                    eframe::run_native(Box::new(app), native_options);
+                }
+                Err(err) => {
+                    println!("{}", err);
+                    process::exit(1);
+                }
+            },
+        },
         Err(err) => {
             println!("{}", err);
+            process::exit(1);
         }
     }
 }